@@ -30,7 +30,7 @@ impl Game for RotBoxGame {
                 texture: "box",
                 pos: Point2::new(w / 2., h / 2.),
                 rot: 0.,
-            }, |data, _, _, delta| {
+            }, |data, _, _, _objects, delta| {
                 data.rot += 0.4 * delta;
             }
         ));
@@ -1,7 +1,10 @@
-use kondi::{ContextConfiguration, Context, Game, GameStateSetup, GgezResult, util::Point2, ggez::event::KeyCode};
+use kondi::{ContextConfiguration, Context, Game, GameStateSetup, GgezResult, State, util::Point2, ggez::event::{KeyCode, Axis, Button}};
+use kondi::input::{Input, AxisDirection};
 use kondi::object::{
     tex_box::{TexBox, TexBoxData},
+    Object, ObjectId, ObjectSet,
 };
+use kondi::physics::{Body, BodyMut};
 
 fn main() {
     ContextConfiguration::new()
@@ -9,12 +12,19 @@ fn main() {
         .unwrap()
 }
 
-struct WalkingBoxGame;
+struct WalkingBoxGame {
+    walking_box: ObjectId<TexBox<'static>>,
+}
 
 const UP: &'static str = "up";
 const DOWN: &'static str = "down";
 const LEFT: &'static str = "left";
 const RIGHT: &'static str = "right";
+const MOVE_X: &'static str = "move_x";
+const MOVE_Y: &'static str = "move_y";
+const MOVE: &'static str = "move";
+const PAUSE: &'static str = "pause";
+const STEP: &'static str = "step";
 
 const SPEED: f32 = 100.;
 
@@ -26,28 +36,48 @@ impl Game for WalkingBoxGame {
         s.bind_keys(DOWN, vec![KeyCode::Down, KeyCode::S]);
         s.bind_keys(LEFT, vec![KeyCode::Left, KeyCode::A]);
         s.bind_keys(RIGHT, vec![KeyCode::Right, KeyCode::D]);
+        s.bind(Button::DPadUp, UP);
+        s.bind(Button::DPadDown, DOWN);
+        s.bind(Button::DPadLeft, LEFT);
+        s.bind(Button::DPadRight, RIGHT);
+        s.bind(Input::PadAxis(None, Axis::LeftStickY, AxisDirection::Positive), UP);
+        s.bind(Input::PadAxis(None, Axis::LeftStickY, AxisDirection::Negative), DOWN);
+        s.bind(Input::PadAxis(None, Axis::LeftStickX, AxisDirection::Negative), LEFT);
+        s.bind(Input::PadAxis(None, Axis::LeftStickX, AxisDirection::Positive), RIGHT);
+
+        s.bind_axis(MOVE_X, LEFT, RIGHT);
+        s.bind_axis(MOVE_Y, UP, DOWN);
+        s.bind_vector(MOVE, MOVE_X, MOVE_Y);
+
+        s.bind_key(KeyCode::P, PAUSE);
+        s.bind_key(KeyCode::Period, STEP);
+        s.add_key_down_handler(PAUSE, Box::new(|_, _, state, _| {
+            state.toggle_pause();
+            Ok(())
+        }));
+        s.add_key_down_handler(STEP, Box::new(|_, _, state, _| {
+            state.step();
+            Ok(())
+        }));
 
-        let _walking_box = s.object_set.add(TexBox::new(
+        let walking_box = s.object_set.add(TexBox::new(
             TexBoxData {
                 texture: "box",
                 pos: Point2::new(w / 2., h / 2.),
                 rot: 0.,
-            }, |data, ctx, state, delta| {
-                if state.is_down(ctx, UP) {
-                    data.pos.y -= SPEED * delta;
-                }
-                if state.is_down(ctx, DOWN) {
-                    data.pos.y += SPEED * delta;
-                }
-                if state.is_down(ctx, LEFT) {
-                    data.pos.x -= SPEED * delta;
-                }
-                if state.is_down(ctx, RIGHT) {
-                    data.pos.x += SPEED * delta;
-                }
-            }
-        ));
-
-        Ok(WalkingBoxGame)
+            }, |_, _, _, _, _| ()
+        ).with_body(Body::kinematic()));
+
+        Ok(WalkingBoxGame {
+            walking_box,
+        })
+    }
+    fn tick(&mut self, _: &mut Context, state: &mut State, object_set: &mut ObjectSet, _delta: f32) -> GgezResult {
+        let vel = state.vector(MOVE) * SPEED;
+        // The body moves the box by its velocity every tick
+        if let Some(BodyMut { body, .. }) = object_set.get_mut(self.walking_box).and_then(|b| b.body_mut()) {
+            body.vel = vel;
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,186 @@
+//! Loading and saving of key bindings
+//!
+//! Bindings are stored as a TOML table mapping action names to
+//! either a single input name or an array of input names:
+//!
+//! ```toml
+//! up = ["Up", "W"]
+//! fire = ["Space", "MouseLeft"]
+//! zoom_in = "WheelUp"
+//! ```
+//!
+//! Key names are the names of the `KeyCode` variants.
+//! Mouse buttons are `MouseLeft`, `MouseRight`, `MouseMiddle` or `Mouse` followed by a number,
+//! and the wheel is `WheelUp`, `WheelDown`, `WheelLeft` or `WheelRight`.
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+use ggez::{Context, GameError, GameResult, filesystem, event::KeyCode};
+use toml::{Value, value::Table};
+
+use crate::State;
+use crate::input::{Input, context::InputContext};
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)*) => {
+        /// Gets the `KeyCode` with the given name
+        pub fn key_from_name(name: &str) -> Option<KeyCode> {
+            match name {
+                $(stringify!($key) => Some(KeyCode::$key),)*
+                _ => None,
+            }
+        }
+        /// Gets the name of a `KeyCode` as used in binding files
+        pub fn key_name(key: KeyCode) -> &'static str {
+            match key {
+                $(KeyCode::$key => stringify!($key),)*
+            }
+        }
+        /// Gets the `KeyCode` with the given number, as made by `key as u32`
+        pub(crate) fn key_from_code(code: u32) -> Option<KeyCode> {
+            const KEYS: &[KeyCode] = &[$(KeyCode::$key,)*];
+            KEYS.iter().copied().find(|&key| key as u32 == code)
+        }
+    };
+}
+
+key_names! {
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15, F16, F17, F18, F19,
+    F20, F21, F22, F23, F24,
+    Snapshot, Scroll, Pause,
+    Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down,
+    Back, Return, Space,
+    Compose, Caret,
+    Numlock, Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7,
+    Numpad8, Numpad9,
+    AbntC1, AbntC2, Add, Apostrophe, Apps, At, Ax, Backslash, Calculator, Capital, Colon,
+    Comma, Convert, Decimal, Divide, Equals, Grave, Kana, Kanji, LAlt, LBracket,
+    LControl, LShift, LWin, Mail, MediaSelect, MediaStop, Minus, Multiply, Mute,
+    MyComputer, NavigateForward, NavigateBackward, NextTrack, NoConvert, NumpadComma,
+    NumpadEnter, NumpadEquals, OEM102, Period, PlayPause, Power, PrevTrack, RAlt,
+    RBracket, RControl, RShift, RWin, Semicolon, Slash, Sleep, Stop, Subtract, Sysrq,
+    Tab, Underline, Unlabeled, VolumeDown, VolumeUp, Wake, WebBack, WebFavorites,
+    WebForward, WebHome, WebRefresh, WebSearch, WebStop, Yen, Copy, Paste, Cut,
+}
+
+#[inline]
+fn config_error<S: Into<String>>(s: S) -> GameError {
+    GameError::ConfigError(s.into())
+}
+
+/// Parses a bindings file into a list of actions and their inputs
+pub fn parse_bindings(s: &str) -> GameResult<Vec<(String, Vec<Input>)>> {
+    let table = match s.parse::<Value>() {
+        Ok(Value::Table(table)) => table,
+        Ok(_) => return Err(config_error("Bindings must be a table of actions")),
+        Err(e) => return Err(config_error(format!("Couldn't parse bindings: {}", e))),
+    };
+
+    let mut bindings = Vec::with_capacity(table.len());
+    for (action, value) in table {
+        let names = match value {
+            Value::String(name) => vec![name],
+            Value::Array(names) => names
+                .into_iter()
+                .map(|v| match v {
+                    Value::String(name) => Ok(name),
+                    v => Err(config_error(format!("Expected input name for action `{}`, found {}", action, v))),
+                })
+                .collect::<GameResult<_>>()?,
+            v => return Err(config_error(format!("Expected input name or list of input names for action `{}`, found {}", action, v))),
+        };
+        let inputs = names
+            .iter()
+            .map(|name| Input::from_name(name)
+                .ok_or_else(|| config_error(format!("Unknown input name `{}` for action `{}`", name, action))))
+            .collect::<GameResult<_>>()?;
+
+        bindings.push((action, inputs));
+    }
+
+    Ok(bindings)
+}
+
+impl<'a> InputContext<'a> {
+    /// Serialises the bindings of the context to a string in the bindings file format
+    ///
+    /// Called on `State` this gives the bindings of the default context.
+    pub fn bindings_to_string(&self) -> GameResult<String> {
+        let table: Table = self.actions()
+            .map(|(name, inputs)| {
+                let mut names: Vec<_> = inputs.iter().map(|&i| i.name()).collect();
+                names.sort();
+                let names = names.into_iter().map(Value::String).collect();
+                (name.to_string(), Value::Array(names))
+            })
+            .collect();
+
+        toml::to_string(&Value::Table(table))
+            .map_err(|e| config_error(format!("Couldn't serialise bindings: {}", e)))
+    }
+}
+
+impl<'a> State<'a> {
+    /// Loads the bindings in the file at `path` from the ggez filesystem into the default context
+    ///
+    /// Actions in the file replace any inputs already bound to them,
+    /// actions not mentioned are left alone.
+    pub fn load_bindings(&mut self, ctx: &mut Context, path: &str) -> GameResult {
+        let mut s = String::new();
+        filesystem::open(ctx, path)?.read_to_string(&mut s)?;
+
+        for (action, inputs) in parse_bindings(&s)? {
+            self.rebind(Cow::Owned(action), inputs);
+        }
+        Ok(())
+    }
+    /// Writes all current bindings to `path` in the user config directory
+    pub fn save_bindings(&self, ctx: &mut Context, path: &str) -> GameResult {
+        let mut file = filesystem::create(ctx, path)?;
+        file.write_all(self.bindings_to_string()?.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use ggez::event::{Axis, Button, KeyMods, MouseButton};
+
+    use super::*;
+    use crate::input::{AxisDirection, Pad, WheelDirection};
+
+    #[test]
+    fn bindings_read_back_the_same() {
+        let mut context = InputContext::default();
+        context.bind_keys("up", vec![KeyCode::Up, KeyCode::W]);
+        context.bind_mod_key(KeyMods::CTRL | KeyMods::SHIFT, KeyCode::S, "save");
+        context.bind_mouse(MouseButton::Left, "fire");
+        context.bind_mouse(MouseButton::Other(9), "fire");
+        context.bind_wheel(WheelDirection::Up, "zoom in");
+        context.bind_pad_button(Some(Pad(1)), Button::South, "jump");
+        context.bind_pad_axis(None, Axis::LeftStickX, AxisDirection::Negative, "left");
+
+        let s = context.bindings_to_string().unwrap();
+        let mut read = parse_bindings(&s).unwrap();
+        read.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut written: Vec<_> = context.actions().map(|(name, inputs)| (name.to_owned(), inputs.clone())).collect();
+        written.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let read: Vec<_> = read.into_iter().map(|(name, inputs)| (name, inputs.into_iter().collect::<HashSet<_>>())).collect();
+        assert_eq!(read, written);
+    }
+
+    #[test]
+    fn single_names_and_lists_both_parse() {
+        let bindings = parse_bindings("up = [\"Up\", \"W\"]\nzoom_in = \"WheelUp\"").unwrap();
+        assert_eq!(bindings.len(), 2);
+        assert!(parse_bindings("up = \"NotAKey\"").is_err());
+        assert!(parse_bindings("up = 5").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! The camera deciding what part of the world is on the screen
+use ggez::graphics::Rect;
+use nalgebra::{Matrix4, Vector3};
+
+use crate::util::{Vector2, Point2, Rotation2, angle_to_vec};
+use crate::object::{Object, ObjectId, ObjectSet};
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    /// The point in the world shown in the middle of the screen
+    pub pos: Point2,
+    /// How many pixels one unit in the world takes up
+    pub zoom: f32,
+    /// How far the world is turned on the screen in radians
+    ///
+    /// Positive turns it clockwise on the screen, since the y-axis points down:
+    /// with a quarter turn, what's to the right of `pos` in the world is drawn below the middle of the screen.
+    pub rot: f32,
+    /// The object to follow
+    pub target: Option<ObjectId<dyn Object>>,
+    /// How quickly the camera catches up with the target, higher is faster
+    ///
+    /// Infinity snaps to the target right away.
+    pub follow_speed: f32,
+    /// How far the target can move from the middle of the screen
+    /// in each direction before the camera follows
+    pub deadzone: Vector2,
+    /// The part of the world the camera should stay inside
+    pub bounds: Option<Rect>,
+
+    shake_intensity: f32,
+    shake_duration: f32,
+    shake_left: f32,
+    shake_offset: Vector2,
+    rng: u32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            pos: Point2::new(0., 0.),
+            zoom: 1.,
+            rot: 0.,
+            target: None,
+            follow_speed: f32::INFINITY,
+            deadzone: Vector2::new(0., 0.),
+            bounds: None,
+            shake_intensity: 0.,
+            shake_duration: 0.,
+            shake_left: 0.,
+            shake_offset: Vector2::new(0., 0.),
+            rng: 0x9E37_79B9,
+        }
+    }
+}
+
+impl Camera {
+    pub fn new(pos: Point2) -> Self {
+        Camera {
+            pos,
+            .. Self::default()
+        }
+    }
+    /// Starts following an object
+    #[inline]
+    pub fn follow<T: ?Sized>(&mut self, target: ObjectId<T>) {
+        self.target = Some(target.erase());
+    }
+    /// Shakes the camera up to `intensity` units away, calming down over `duration` seconds
+    pub fn shake(&mut self, intensity: f32, duration: f32) {
+        self.shake_intensity = intensity;
+        self.shake_duration = duration;
+        self.shake_left = duration;
+    }
+    /// The point in the world shown in the middle of the screen, including shake
+    #[inline]
+    pub fn centre(&self) -> Point2 {
+        self.pos + self.shake_offset
+    }
+    /// The transformation from the world to the screen
+    pub fn transform(&self, width: f32, height: f32) -> Matrix4<f32> {
+        let centre = self.centre();
+        Matrix4::new_translation(&Vector3::new(width / 2., height / 2., 0.))
+            * Matrix4::new_rotation(Vector3::new(0., 0., self.rot))
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(self.zoom, self.zoom, 1.))
+            * Matrix4::new_translation(&Vector3::new(-centre.x, -centre.y, 0.))
+    }
+    /// Where a point in the world ends up on a screen of the given size
+    pub fn world_to_screen(&self, p: Point2, width: f32, height: f32) -> Point2 {
+        let v = Rotation2::new(self.rot) * ((p - self.centre()) * self.zoom);
+        Point2::new(width / 2., height / 2.) + v
+    }
+    /// Which point in the world is shown at a point on a screen of the given size
+    pub fn screen_to_world(&self, p: Point2, width: f32, height: f32) -> Point2 {
+        let v = p - Point2::new(width / 2., height / 2.);
+        self.centre() + Rotation2::new(-self.rot) * v / self.zoom
+    }
+    /// The part of the world shown on a screen of the given size
+    ///
+    /// When the camera is rotated this is the smallest rectangle around what's shown.
+    pub fn view(&self, width: f32, height: f32) -> Rect {
+        let first = self.screen_to_world(Point2::new(0., 0.), width, height);
+        let (mut min, mut max) = (first, first);
+        for &(x, y) in &[(width, 0.), (0., height), (width, height)] {
+            let p = self.screen_to_world(Point2::new(x, y), width, height);
+            min = Point2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Point2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+    /// A small deterministic random number from -1 to 1
+    fn random(&mut self) -> f32 {
+        // xorshift32
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng as f32 / u32::MAX as f32) * 2. - 1.
+    }
+    /// Moves the camera towards its target, keeps it in bounds and shakes it
+    pub fn update(&mut self, object_set: &ObjectSet, width: f32, height: f32, delta: f32) {
+        if let Some(target) = self.target {
+            match object_set.get_dyn(target).and_then(|obj| obj.pos()) {
+                Some(p) => self.follow_point(p, delta),
+                // The target is gone
+                None => self.target = None,
+            }
+        }
+        if let Some(bounds) = self.bounds {
+            self.clamp_to(bounds, width, height);
+        }
+
+        if self.shake_left > 0. {
+            self.shake_left = (self.shake_left - delta).max(0.);
+            let strength = self.shake_intensity * self.shake_left / self.shake_duration;
+            let angle = self.random() * std::f32::consts::PI;
+            self.shake_offset = angle_to_vec(angle) * strength * self.random().abs();
+        } else {
+            self.shake_offset = Vector2::new(0., 0.);
+        }
+    }
+    fn follow_point(&mut self, p: Point2, delta: f32) {
+        let diff = p - self.pos;
+        let outside = |d: f32, dz: f32| if d.abs() > dz { d - dz * d.signum() } else { 0. };
+        let diff = Vector2::new(outside(diff.x, self.deadzone.x), outside(diff.y, self.deadzone.y));
+
+        let amount = if self.follow_speed.is_infinite() {
+            1.
+        } else {
+            1. - (-self.follow_speed * delta).exp()
+        };
+        self.pos += diff * amount;
+    }
+    fn clamp_to(&mut self, bounds: Rect, width: f32, height: f32) {
+        let half_w = width / 2. / self.zoom;
+        let half_h = height / 2. / self.zoom;
+        let clamp = |p: f32, min: f32, size: f32, half: f32| {
+            if size <= 2. * half {
+                min + size / 2.
+            } else {
+                p.max(min + half).min(min + size - half)
+            }
+        };
+        self.pos.x = clamp(self.pos.x, bounds.x, bounds.w, half_w);
+        self.pos.y = clamp(self.pos.y, bounds.y, bounds.h, half_h);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+
+    use super::*;
+
+    fn close(a: Point2, b: Point2) -> bool {
+        (a - b).norm() < 1e-3
+    }
+
+    fn cameras() -> Vec<Camera> {
+        [(0., 1.), (0.7, 1.), (0., 2.5), (-2.1, 0.4)].iter()
+            .map(|&(rot, zoom)| Camera {
+                rot,
+                zoom,
+                .. Camera::new(Point2::new(120., -35.))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn positive_rotation_turns_the_world_clockwise_on_the_screen() {
+        let camera = Camera {
+            rot: std::f32::consts::FRAC_PI_2,
+            .. Camera::new(Point2::new(0., 0.))
+        };
+        let right = camera.world_to_screen(Point2::new(10., 0.), 800., 600.);
+        assert!(close(right, Point2::new(400., 310.)), "{:?}", right);
+    }
+
+    #[test]
+    fn screen_to_world_undoes_world_to_screen() {
+        let (width, height) = (800., 600.);
+        for camera in cameras() {
+            for &p in &[Point2::new(0., 0.), Point2::new(120., -35.), Point2::new(-300., 410.5)] {
+                let back = camera.screen_to_world(camera.world_to_screen(p, width, height), width, height);
+                assert!(close(back, p), "{:?} came back as {:?} with {:?}", p, back, camera);
+            }
+        }
+    }
+
+    #[test]
+    fn transform_matches_world_to_screen() {
+        let (width, height) = (800., 600.);
+        for camera in cameras() {
+            let p = Point2::new(-300., 410.5);
+            let v = camera.transform(width, height) * Vector4::new(p.x, p.y, 0., 1.);
+            assert!(close(Point2::new(v.x, v.y), camera.world_to_screen(p, width, height)));
+        }
+    }
+
+    #[test]
+    fn view_covers_the_screen() {
+        let (width, height) = (800., 600.);
+        for camera in cameras() {
+            let view = camera.view(width, height);
+            for &(x, y) in &[(0., 0.), (width, 0.), (0., height), (width, height), (width / 2., height / 2.)] {
+                let p = camera.screen_to_world(Point2::new(x, y), width, height);
+                assert!(p.x >= view.x - 1e-3 && p.x <= view.x + view.w + 1e-3);
+                assert!(p.y >= view.y - 1e-3 && p.y <= view.y + view.h + 1e-3);
+            }
+        }
+        let camera = Camera::new(Point2::new(10., 20.));
+        assert_eq!(camera.view(width, height), Rect::new(-390., -280., 800., 600.));
+    }
+}
@@ -0,0 +1,283 @@
+//! Collider shapes and collision detection between objects
+use std::collections::HashSet;
+
+use ggez::graphics::Rect;
+
+use crate::util::{Vector2, Point2, angle_to_vec};
+use crate::object::{Object, ObjectId, ObjectSet};
+
+/// The shape of a collider around its position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// A box with the given half width and half height that ignores rotation
+    Aabb(Vector2),
+    /// A circle with the given radius
+    Circle(f32),
+    /// A box with the given half width and half height that follows the rotation
+    Obb(Vector2),
+}
+
+/// A shape placed in the world
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collider {
+    pub pos: Point2,
+    pub rot: f32,
+    pub shape: Shape,
+}
+
+/// How two colliders overlap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    /// Unit vector pointing from the first collider towards the second
+    pub normal: Vector2,
+    /// How far the colliders have to be pushed apart along `normal` to stop overlapping
+    pub depth: f32,
+}
+
+impl Contact {
+    /// The same contact seen from the other collider
+    #[inline]
+    pub fn flipped(self) -> Self {
+        Contact {
+            normal: -self.normal,
+            .. self
+        }
+    }
+}
+
+impl Collider {
+    #[inline]
+    pub fn new(pos: Point2, shape: Shape) -> Self {
+        Collider {
+            pos,
+            rot: 0.,
+            shape,
+        }
+    }
+    #[inline]
+    pub fn rotated(self, rot: f32) -> Self {
+        Collider {
+            rot,
+            .. self
+        }
+    }
+    /// The local x and y axes and half extents if this is a box
+    fn box_axes(&self) -> Option<([Vector2; 2], Vector2)> {
+        match self.shape {
+            Shape::Aabb(half) => Some(([Vector2::x(), Vector2::y()], half)),
+            Shape::Obb(half) => {
+                let x = angle_to_vec(self.rot);
+                Some(([x, Vector2::new(-x.y, x.x)], half))
+            }
+            Shape::Circle(_) => None,
+        }
+    }
+    /// The smallest axis-aligned rectangle containing the collider
+    pub fn bounds(&self) -> Rect {
+        let ext = match self.box_axes() {
+            Some(([x, y], half)) => Vector2::new(
+                (x.x * half.x).abs() + (y.x * half.y).abs(),
+                (x.y * half.x).abs() + (y.y * half.y).abs(),
+            ),
+            None => match self.shape {
+                Shape::Circle(r) => Vector2::new(r, r),
+                _ => unreachable!(),
+            },
+        };
+        Rect::new(self.pos.x - ext.x, self.pos.y - ext.y, 2. * ext.x, 2. * ext.y)
+    }
+    /// Checks whether two colliders overlap
+    pub fn collide(&self, other: &Collider) -> Option<Contact> {
+        match (self.box_axes(), other.box_axes()) {
+            (Some(a), Some(b)) => box_box(self.pos, a, other.pos, b),
+            (Some(a), None) => box_circle(self.pos, a, other.pos, other.radius()),
+            (None, Some(b)) => box_circle(other.pos, b, self.pos, self.radius()).map(Contact::flipped),
+            (None, None) => circle_circle(self.pos, self.radius(), other.pos, other.radius()),
+        }
+    }
+    /// Checks whether a point is inside the collider
+    pub fn contains(&self, p: Point2) -> bool {
+        let d = p - self.pos;
+        match self.box_axes() {
+            Some(([x, y], half)) => d.dot(&x).abs() <= half.x && d.dot(&y).abs() <= half.y,
+            None => d.norm() <= self.radius(),
+        }
+    }
+    #[inline]
+    fn radius(&self) -> f32 {
+        match self.shape {
+            Shape::Circle(r) => r,
+            _ => 0.,
+        }
+    }
+}
+
+fn circle_circle(a: Point2, ra: f32, b: Point2, rb: f32) -> Option<Contact> {
+    let d = b - a;
+    let dist = d.norm();
+    let depth = ra + rb - dist;
+    if depth <= 0. {
+        return None;
+    }
+    let normal = if dist > 0. { d / dist } else { Vector2::x() };
+    Some(Contact { normal, depth })
+}
+
+fn box_circle(a: Point2, ([ax, ay], half): ([Vector2; 2], Vector2), b: Point2, r: f32) -> Option<Contact> {
+    let d = b - a;
+    let local = Vector2::new(d.dot(&ax), d.dot(&ay));
+    let clamped = Vector2::new(local.x.max(-half.x).min(half.x), local.y.max(-half.y).min(half.y));
+
+    let (local_normal, depth) = if clamped == local {
+        // The centre of the circle is inside the box, push it out the nearest side
+        let (dx, dy) = (half.x - local.x.abs(), half.y - local.y.abs());
+        if dx < dy {
+            (Vector2::new(local.x.signum(), 0.), r + dx)
+        } else {
+            (Vector2::new(0., local.y.signum()), r + dy)
+        }
+    } else {
+        let diff = local - clamped;
+        let dist = diff.norm();
+        if dist >= r {
+            return None;
+        }
+        (diff / dist, r - dist)
+    };
+
+    Some(Contact {
+        normal: ax * local_normal.x + ay * local_normal.y,
+        depth,
+    })
+}
+
+fn box_box(a: Point2, (axes_a, half_a): ([Vector2; 2], Vector2), b: Point2, (axes_b, half_b): ([Vector2; 2], Vector2)) -> Option<Contact> {
+    let d = b - a;
+    let radius = |[x, y]: [Vector2; 2], half: Vector2, axis: &Vector2| {
+        (x.dot(axis) * half.x).abs() + (y.dot(axis) * half.y).abs()
+    };
+
+    let mut best: Option<Contact> = None;
+    for axis in axes_a.iter().chain(axes_b.iter()) {
+        let dist = d.dot(axis);
+        let depth = radius(axes_a, half_a, axis) + radius(axes_b, half_b, axis) - dist.abs();
+        // Found a separating axis
+        if depth <= 0. {
+            return None;
+        }
+        if best.map(|c| depth < c.depth).unwrap_or(true) {
+            let normal = if dist < 0. { -*axis } else { *axis };
+            best = Some(Contact { normal, depth });
+        }
+    }
+    best
+}
+
+#[inline]
+fn overlaps_y(a: &Rect, b: &Rect) -> bool {
+    a.y <= b.y + b.h && b.y <= a.y + a.h
+}
+
+/// Pairs of overlapping objects and how they overlap
+pub type Contacts = Vec<(ObjectId<dyn Object>, ObjectId<dyn Object>, Contact)>;
+
+/// Finds all pairs of overlapping objects
+///
+/// The first id of each pair is always the one in the lower slot,
+/// so the pairs come out in the same order every time.
+pub fn contacts(object_set: &ObjectSet) -> Contacts {
+    let mut colliders: Vec<_> = object_set.iter_with_ids()
+        .filter_map(|(id, obj)| obj.collider().map(|c| (id, c, c.bounds())))
+        .collect();
+    // Sweep along the x-axis so only objects that overlap horizontally are checked
+    colliders.sort_by(|a, b| a.2.x.partial_cmp(&b.2.x).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut contacts = Vec::new();
+    for (i, &(id_a, a, bounds_a)) in colliders.iter().enumerate() {
+        for &(id_b, b, bounds_b) in &colliders[i+1..] {
+            if bounds_b.x > bounds_a.x + bounds_a.w {
+                break;
+            }
+            if !overlaps_y(&bounds_a, &bounds_b) {
+                continue;
+            }
+            if let Some(contact) = a.collide(&b) {
+                if id_a.index() < id_b.index() {
+                    contacts.push((id_a, id_b, contact));
+                } else {
+                    contacts.push((id_b, id_a, contact.flipped()));
+                }
+            }
+        }
+    }
+    contacts.sort_by_key(|&(a, b, _)| (a.index(), b.index()));
+    contacts
+}
+
+/// Whether a collision just started, is still going on or just ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CollisionPhase {
+    Start,
+    Stay,
+    End,
+}
+
+/// A collision between two objects
+#[derive(Debug, Clone, Copy)]
+pub struct Collision {
+    pub a: ObjectId<dyn Object>,
+    pub b: ObjectId<dyn Object>,
+    pub phase: CollisionPhase,
+    /// How the objects overlap, `None` when the collision has ended
+    pub contact: Option<Contact>,
+}
+
+impl Collision {
+    /// The same collision seen from `b`
+    #[inline]
+    pub fn flipped(self) -> Self {
+        Collision {
+            a: self.b,
+            b: self.a,
+            contact: self.contact.map(Contact::flipped),
+            .. self
+        }
+    }
+}
+
+/// Remembers which objects were touching last tick to tell the phases apart
+#[derive(Debug, Default)]
+pub(crate) struct CollisionTracker {
+    touching: HashSet<(ObjectId<dyn Object>, ObjectId<dyn Object>)>,
+}
+
+impl CollisionTracker {
+    /// Turns the contacts found this tick into collisions
+    pub(crate) fn update(&mut self, contacts: Contacts) -> Vec<Collision> {
+        let mut touching = HashSet::with_capacity(contacts.len());
+
+        let mut collisions: Vec<_> = contacts.into_iter()
+            .map(|(a, b, contact)| {
+                touching.insert((a, b));
+                let phase = if self.touching.contains(&(a, b)) {
+                    CollisionPhase::Stay
+                } else {
+                    CollisionPhase::Start
+                };
+                Collision { a, b, phase, contact: Some(contact) }
+            })
+            .collect();
+
+        let mut ended: Vec<_> = self.touching.difference(&touching).copied().collect();
+        ended.sort_by_key(|&(a, b)| (a.index(), b.index()));
+        collisions.extend(ended.into_iter().map(|(a, b)| Collision {
+            a,
+            b,
+            phase: CollisionPhase::End,
+            contact: None,
+        }));
+
+        self.touching = touching;
+        collisions
+    }
+}
@@ -0,0 +1,124 @@
+//! Driving a game tick by tick without the event loop, for tests
+//!
+//! Nothing is drawn and no assets are loaded, the default font is used
+//! and images are only loaded if an object draws itself.
+//!
+//! ggez 0.5 can't make a `Context` without a window and every handler takes one,
+//! so a window is still opened and a display is needed, like `xvfb-run cargo test` on a CI box.
+//! No events are read from the window.
+//!
+//! Tests that have to run without a display can't use this,
+//! they have to stick to logic that doesn't need a `Context`.
+use ggez::event::{EventsLoop, KeyCode, KeyMods, MouseButton};
+
+use crate::{Context, ContextConfiguration, Game, GameState, GgezResult, State};
+use crate::textures::Textures;
+use crate::util::Point2;
+use crate::input::InputEvent;
+use crate::object::ObjectSet;
+
+/// A game run by calling `run_ticks` instead of by the event loop
+///
+/// Input can be sent between ticks with `send`, or scripted ahead of time
+/// by playing a `Recording` with `State::play`.
+pub struct Headless<'a, G: Game> {
+    ctx: Context,
+    /// Kept around so the window stays open
+    _events: EventsLoop,
+    pub(crate) game_state: GameState<'a, G>,
+}
+
+impl ContextConfiguration {
+    /// Sets up the game to be run tick by tick
+    pub fn headless<'a, G: Game>(self) -> GgezResult<Headless<'a, G>> {
+        let tick_rate = self.tick_rate;
+        let (mut ctx, events) = self.build()?;
+        let game_state = GameState::new(&mut ctx, tick_rate, Textures::without_assets())?;
+        Ok(Headless {
+            ctx,
+            _events: events,
+            game_state,
+        })
+    }
+}
+
+impl<'a, G: Game> Headless<'a, G> {
+    #[inline]
+    pub fn ctx(&mut self) -> &mut Context {
+        &mut self.ctx
+    }
+    #[inline]
+    pub fn game(&self) -> &G {
+        &self.game_state.game
+    }
+    #[inline]
+    pub fn game_mut(&mut self) -> &mut G {
+        &mut self.game_state.game
+    }
+    #[inline]
+    pub fn state(&self) -> &State<'a> {
+        &self.game_state.state
+    }
+    #[inline]
+    pub fn state_mut(&mut self) -> &mut State<'a> {
+        &mut self.game_state.state
+    }
+    #[inline]
+    pub fn object_set(&self) -> &ObjectSet {
+        &self.game_state.object_set
+    }
+    #[inline]
+    pub fn object_set_mut(&mut self) -> &mut ObjectSet {
+        &mut self.game_state.object_set
+    }
+    /// Handles an input event right away, as if it happened after the last tick
+    pub fn send(&mut self, event: InputEvent) -> GgezResult {
+        self.game_state.input_event(&mut self.ctx, event);
+        self.take_error()
+    }
+    #[inline]
+    pub fn press_key(&mut self, key: KeyCode) -> GgezResult {
+        self.send(InputEvent::KeyDown { key, mods: KeyMods::empty(), repeat: false })
+    }
+    #[inline]
+    pub fn release_key(&mut self, key: KeyCode) -> GgezResult {
+        self.send(InputEvent::KeyUp { key, mods: KeyMods::empty() })
+    }
+    /// Moves the mouse to a point on the screen
+    #[inline]
+    pub fn move_mouse(&mut self, pos: Point2) -> GgezResult {
+        self.send(InputEvent::MouseMotion { pos })
+    }
+    /// Presses and releases a mouse button at a point on the screen
+    pub fn click(&mut self, button: MouseButton, pos: Point2) -> GgezResult {
+        self.send(InputEvent::MouseDown { button, pos })?;
+        self.send(InputEvent::MouseUp { button, pos })
+    }
+    /// Runs `n` ticks, each after a call to `Game::logic` as if every update had one tick
+    ///
+    /// This goes around pausing and `State::time_scale`, and skips `update_unscaled`.
+    /// Use `update` to test those.
+    ///
+    /// Scene transitions go on for one tick's time per tick.
+    pub fn run_ticks(&mut self, n: u32) -> GgezResult {
+        for _ in 0..n {
+            self.game_state.before_ticks(&mut self.ctx)?;
+            self.game_state.tick(&mut self.ctx)?;
+        }
+        self.take_error()
+    }
+    /// Runs one update as the event loop would `frame_time` seconds after the last
+    ///
+    /// Pausing, `State::time_scale`, `State::step` and `update_unscaled` all work like in the game.
+    pub fn update(&mut self, frame_time: f32) -> GgezResult {
+        self.game_state.update_by(&mut self.ctx, frame_time)?;
+        self.take_error()
+    }
+    /// Returns the error a handler gave, if any
+    fn take_error(&mut self) -> GgezResult {
+        match self.game_state.state.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Inputs that can be bound to actions
+use ggez::event::{KeyCode, KeyMods, MouseButton, Axis, Button};
+
+use crate::util::{Point2, Vector2};
+use crate::bindings::{key_from_name, key_name};
+
+pub mod context;
+
+/// A direction the mouse wheel can be scrolled in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WheelDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// A gamepad, numbered by the order they were first used in
+///
+/// The first gamepad to be used is player 0, the next player 1 and so on.
+/// A gamepad keeps its number if it is disconnected and connected again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pad(pub usize);
+
+/// Which way an analog axis is pushed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AxisDirection {
+    Negative,
+    Positive,
+}
+
+impl AxisDirection {
+    /// Whether an axis at `value` counts as pushed this way past the deadzone
+    #[inline]
+    pub fn pushed(self, value: f32, deadzone: f32) -> bool {
+        match self {
+            AxisDirection::Negative => value < -deadzone,
+            AxisDirection::Positive => value > deadzone,
+        }
+    }
+}
+
+/// Scales an axis value so the deadzone is 0 and the rest still goes from 0 to 1
+///
+/// A deadzone of 1 or more swallows the whole axis, and a negative one counts as 0.
+pub(crate) fn past_deadzone(value: f32, deadzone: f32) -> f32 {
+    // Clamping the value keeps it inside a deadzone of 1 or more, so this never divides by 0
+    let value = value.clamp(-1., 1.);
+    let deadzone = deadzone.max(0.);
+    if value.abs() <= deadzone {
+        0.
+    } else {
+        value.signum() * (value.abs() - deadzone) / (1. - deadzone)
+    }
+}
+
+/// Something that happened to a gamepad
+///
+/// Real gamepad events are turned into these, and more can be made up
+/// with `State::inject_pad_event` to act as a gamepad without one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PadEvent {
+    Connected(Pad),
+    Disconnected(Pad),
+    ButtonDown(Pad, Button),
+    ButtonUp(Pad, Button),
+    /// An axis moved to a value from -1 to 1
+    Axis(Pad, Axis, f32),
+}
+
+/// Something that can be bound to an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+    /// A key pressed without a more specific binding for the modifiers held with it
+    Key(KeyCode),
+    /// A key pressed while holding exactly the given modifiers
+    ModKey(KeyMods, KeyCode),
+    Mouse(MouseButton),
+    /// Fires the down, press and up handlers of the action at once, is never held down
+    Wheel(WheelDirection),
+    /// A button on the given gamepad, or on any gamepad if `None`
+    PadButton(Option<Pad>, Button),
+    /// An axis on the given gamepad, or on any gamepad if `None`,
+    /// held down while pushed past the deadzone in the direction
+    PadAxis(Option<Pad>, Axis, AxisDirection),
+}
+
+impl From<KeyCode> for Input {
+    #[inline]
+    fn from(key: KeyCode) -> Self {
+        Input::Key(key)
+    }
+}
+impl From<MouseButton> for Input {
+    #[inline]
+    fn from(button: MouseButton) -> Self {
+        Input::Mouse(button)
+    }
+}
+impl From<WheelDirection> for Input {
+    #[inline]
+    fn from(dir: WheelDirection) -> Self {
+        Input::Wheel(dir)
+    }
+}
+
+impl From<Button> for Input {
+    #[inline]
+    fn from(button: Button) -> Self {
+        Input::PadButton(None, button)
+    }
+}
+
+const MODIFIERS: &[(KeyMods, &str)] = &[
+    (KeyMods::CTRL, "Ctrl"),
+    (KeyMods::SHIFT, "Shift"),
+    (KeyMods::ALT, "Alt"),
+    (KeyMods::LOGO, "Logo"),
+];
+
+/// Parses a key with modifiers like `Ctrl+S` or `Ctrl+Shift+Z`
+fn mod_key_from_name(mut name: &str) -> Option<Input> {
+    let mut mods = KeyMods::empty();
+    'prefixes: loop {
+        for &(modifier, mod_name) in MODIFIERS {
+            if let Some(rest) = name.strip_prefix(mod_name).and_then(|rest| rest.strip_prefix('+')) {
+                mods |= modifier;
+                name = rest;
+                continue 'prefixes;
+            }
+        }
+        break;
+    }
+    if mods.is_empty() {
+        return None;
+    }
+    key_from_name(name).map(|key| Input::ModKey(mods, key))
+}
+
+const PAD_BUTTONS: &[(Button, &str)] = &[
+    (Button::South, "South"),
+    (Button::East, "East"),
+    (Button::North, "North"),
+    (Button::West, "West"),
+    (Button::C, "C"),
+    (Button::Z, "Z"),
+    (Button::LeftTrigger, "LeftTrigger"),
+    (Button::LeftTrigger2, "LeftTrigger2"),
+    (Button::RightTrigger, "RightTrigger"),
+    (Button::RightTrigger2, "RightTrigger2"),
+    (Button::Select, "Select"),
+    (Button::Start, "Start"),
+    (Button::Mode, "Mode"),
+    (Button::LeftThumb, "LeftThumb"),
+    (Button::RightThumb, "RightThumb"),
+    (Button::DPadUp, "DPadUp"),
+    (Button::DPadDown, "DPadDown"),
+    (Button::DPadLeft, "DPadLeft"),
+    (Button::DPadRight, "DPadRight"),
+    (Button::Unknown, "Unknown"),
+];
+
+const PAD_AXES: &[(Axis, &str)] = &[
+    (Axis::LeftStickX, "LeftStickX"),
+    (Axis::LeftStickY, "LeftStickY"),
+    (Axis::LeftZ, "LeftZ"),
+    (Axis::RightStickX, "RightStickX"),
+    (Axis::RightStickY, "RightStickY"),
+    (Axis::RightZ, "RightZ"),
+    (Axis::DPadX, "DPadX"),
+    (Axis::DPadY, "DPadY"),
+    (Axis::Unknown, "Unknown"),
+];
+
+/// Gets the `Button` with the given number, as made by `button as u16`
+pub(crate) fn button_from_code(code: u16) -> Option<Button> {
+    PAD_BUTTONS.iter().map(|&(button, _)| button).find(|&button| button as u16 == code)
+}
+
+/// Gets the `Axis` with the given number, as made by `axis as u16`
+pub(crate) fn axis_from_code(code: u16) -> Option<Axis> {
+    PAD_AXES.iter().map(|&(axis, _)| axis).find(|&axis| axis as u16 == code)
+}
+
+fn pad_prefix(pad: Option<Pad>) -> String {
+    match pad {
+        Some(Pad(n)) => format!("Pad{}:", n),
+        None => "Pad:".to_owned(),
+    }
+}
+
+/// Parses a gamepad input name like `Pad:South`, `Pad1:DPadUp` or `Pad0:LeftStickX+`
+fn pad_from_name(name: &str) -> Option<Input> {
+    let rest = name.strip_prefix("Pad")?;
+    let colon = rest.find(':')?;
+    let pad = match &rest[..colon] {
+        "" => None,
+        n => Some(Pad(n.parse().ok()?)),
+    };
+    let rest = &rest[colon+1..];
+
+    let (rest, dir) = if let Some(axis) = rest.strip_suffix('+') {
+        (axis, Some(AxisDirection::Positive))
+    } else if let Some(axis) = rest.strip_suffix('-') {
+        (axis, Some(AxisDirection::Negative))
+    } else {
+        (rest, None)
+    };
+    match dir {
+        Some(dir) => PAD_AXES.iter()
+            .find(|&&(_, n)| n == rest)
+            .map(|&(axis, _)| Input::PadAxis(pad, axis, dir)),
+        None => PAD_BUTTONS.iter()
+            .find(|&&(_, n)| n == rest)
+            .map(|&(button, _)| Input::PadButton(pad, button)),
+    }
+}
+
+impl Input {
+    /// The name used for this input in binding files
+    pub fn name(self) -> String {
+        match self {
+            Input::Key(key) => key_name(key).to_owned(),
+            Input::ModKey(mods, key) => {
+                let mut name = String::new();
+                for &(modifier, mod_name) in MODIFIERS {
+                    if mods.contains(modifier) {
+                        name.push_str(mod_name);
+                        name.push('+');
+                    }
+                }
+                name.push_str(key_name(key));
+                name
+            }
+            Input::Mouse(MouseButton::Left) => "MouseLeft".to_owned(),
+            Input::Mouse(MouseButton::Right) => "MouseRight".to_owned(),
+            Input::Mouse(MouseButton::Middle) => "MouseMiddle".to_owned(),
+            Input::Mouse(MouseButton::Other(n)) => format!("Mouse{}", n),
+            Input::Wheel(WheelDirection::Up) => "WheelUp".to_owned(),
+            Input::Wheel(WheelDirection::Down) => "WheelDown".to_owned(),
+            Input::Wheel(WheelDirection::Left) => "WheelLeft".to_owned(),
+            Input::Wheel(WheelDirection::Right) => "WheelRight".to_owned(),
+            Input::PadButton(pad, button) => {
+                let name = PAD_BUTTONS.iter().find(|&&(b, _)| b == button).map(|&(_, n)| n).unwrap_or("Unknown");
+                format!("{}{}", pad_prefix(pad), name)
+            }
+            Input::PadAxis(pad, axis, dir) => {
+                let name = PAD_AXES.iter().find(|&&(a, _)| a == axis).map(|&(_, n)| n).unwrap_or("Unknown");
+                let sign = match dir {
+                    AxisDirection::Negative => '-',
+                    AxisDirection::Positive => '+',
+                };
+                format!("{}{}{}", pad_prefix(pad), name, sign)
+            }
+        }
+    }
+    /// Gets the input with the given name from a binding file
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "MouseLeft" => Input::Mouse(MouseButton::Left),
+            "MouseRight" => Input::Mouse(MouseButton::Right),
+            "MouseMiddle" => Input::Mouse(MouseButton::Middle),
+            "WheelUp" => Input::Wheel(WheelDirection::Up),
+            "WheelDown" => Input::Wheel(WheelDirection::Down),
+            "WheelLeft" => Input::Wheel(WheelDirection::Left),
+            "WheelRight" => Input::Wheel(WheelDirection::Right),
+            _ => {
+                if let Some(key) = key_from_name(name) {
+                    Input::Key(key)
+                } else if let Some(input) = mod_key_from_name(name) {
+                    input
+                } else if let Some(n) = name.strip_prefix("Mouse") {
+                    Input::Mouse(MouseButton::Other(n.parse().ok()?))
+                } else if name.starts_with("Pad") {
+                    return pad_from_name(name);
+                } else {
+                    return None;
+                }
+            }
+        })
+    }
+}
+
+/// An input event as it comes from ggez, before it's turned into actions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    KeyDown {
+        key: KeyCode,
+        mods: KeyMods,
+        repeat: bool,
+    },
+    KeyUp {
+        key: KeyCode,
+        mods: KeyMods,
+    },
+    /// A mouse button pressed at a point on the screen
+    MouseDown {
+        button: MouseButton,
+        pos: Point2,
+    },
+    /// A mouse button released at a point on the screen
+    MouseUp {
+        button: MouseButton,
+        pos: Point2,
+    },
+    /// The mouse moved to a point on the screen
+    MouseMotion {
+        pos: Point2,
+    },
+    Wheel {
+        x: f32,
+        y: f32,
+    },
+    Pad(PadEvent),
+}
+
+/// A position of the mouse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MousePos {
+    pub screen: Point2,
+    pub world: Point2,
+}
+
+/// Whether a drag just started, is going on or just ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DragPhase {
+    Start,
+    Move,
+    End,
+}
+
+/// Dragging with a mouse button held down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drag {
+    pub phase: DragPhase,
+    pub button: MouseButton,
+    /// Where the button was pressed
+    pub start: MousePos,
+    pub pos: MousePos,
+    /// How far the mouse moved on the screen since the last drag event
+    pub delta: Vector2,
+}
+
+/// How far the mouse has to move with a button held down before it counts as a drag
+pub const DRAG_THRESHOLD: f32 = 4.;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axes_are_scaled_past_the_deadzone() {
+        assert_eq!(past_deadzone(0.1, 0.2), 0.);
+        assert_eq!(past_deadzone(-0.75, 0.5), -0.5);
+        assert_eq!(past_deadzone(1., 0.2), 1.);
+        assert_eq!(past_deadzone(0.5, -1.), 0.5);
+        for &deadzone in &[1., 2., f32::INFINITY, f32::NAN] {
+            for &value in &[-1.5, -1., 0., 1., 1.5] {
+                let scaled = past_deadzone(value, deadzone);
+                assert!(scaled.is_finite() && scaled.abs() <= 1., "{} with deadzone {} gave {}", value, deadzone, scaled);
+            }
+        }
+    }
+}
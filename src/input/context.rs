@@ -0,0 +1,297 @@
+//! Sets of bindings that can be stacked on top of each other
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use ggez::event::{KeyCode, KeyMods, MouseButton, Axis, Button};
+
+use super::{Input, WheelDirection, Pad, AxisDirection};
+
+/// The context at the bottom of the stack, which `State` binds in
+pub const DEFAULT_CONTEXT: &str = "default";
+
+/// Inputs that have to be held down together to trigger an action
+#[derive(Debug, Clone)]
+struct Chord<'a> {
+    inputs: Vec<Input>,
+    name: Cow<'a, str>,
+    active: bool,
+}
+
+/// Inputs that have to be pressed one after the other to trigger an action
+#[derive(Debug, Clone)]
+struct Sequence<'a> {
+    inputs: Vec<Input>,
+    /// The longest time allowed between two presses in seconds
+    max_gap: f32,
+    name: Cow<'a, str>,
+}
+
+/// Whether a press of `input` is a press of `wanted` in a chord
+///
+/// A key pressed with modifiers still counts as the bare key,
+/// so Ctrl and S can be a chord even when Ctrl+S is bound to something else.
+#[inline]
+fn counts_as(input: Input, wanted: Input) -> bool {
+    match (input, wanted) {
+        (Input::ModKey(_, key), Input::Key(wanted)) => key == wanted,
+        _ => input == wanted,
+    }
+}
+
+/// The bindings of one input context, like gameplay, a menu or text entry
+#[derive(Debug, Clone, Default)]
+pub struct InputContext<'a> {
+    /// Whether inputs this context doesn't bind go on to the contexts below it
+    ///
+    /// Inputs bound in a context never reach the ones below.
+    pub pass_through: bool,
+    input_to_name: HashMap<Input, Cow<'a, str>>,
+    name_to_inputs: HashMap<Cow<'a, str>, HashSet<Input>>,
+    /// Axis actions and their negative and positive actions
+    axis_actions: HashMap<Cow<'a, str>, (Cow<'a, str>, Cow<'a, str>)>,
+    /// Vector actions and their x and y axis actions
+    vector_actions: HashMap<Cow<'a, str>, (Cow<'a, str>, Cow<'a, str>)>,
+    chords: Vec<Chord<'a>>,
+    sequences: Vec<Sequence<'a>>,
+}
+
+impl<'a> InputContext<'a> {
+    #[inline]
+    pub fn new(pass_through: bool) -> Self {
+        InputContext {
+            pass_through,
+            .. Self::default()
+        }
+    }
+    /// Binds an input to the action `name`
+    #[inline]
+    pub fn bind<I: Into<Input>>(&mut self, input: I, name: &'a str) {
+        let input = input.into();
+        self.input_to_name.insert(input, Cow::Borrowed(name));
+        self.name_to_inputs.entry(Cow::Borrowed(name)).or_default().insert(input);
+    }
+    #[inline]
+    pub fn bind_key(&mut self, key: KeyCode, name: &'a str) {
+        self.bind(key, name)
+    }
+    #[inline]
+    pub fn bind_keys(&mut self, name: &'a str, keys: Vec<KeyCode>) {
+        for key in keys {
+            self.bind(key, name);
+        }
+    }
+    #[inline]
+    pub fn bind_mouse(&mut self, button: MouseButton, name: &'a str) {
+        self.bind(button, name)
+    }
+    #[inline]
+    pub fn bind_wheel(&mut self, dir: WheelDirection, name: &'a str) {
+        self.bind(dir, name)
+    }
+    /// Binds a button on the given gamepad, or on any gamepad if `None`
+    #[inline]
+    pub fn bind_pad_button(&mut self, pad: Option<Pad>, button: Button, name: &'a str) {
+        self.bind(Input::PadButton(pad, button), name)
+    }
+    /// Binds pushing an axis in a direction on the given gamepad, or on any gamepad if `None`
+    #[inline]
+    pub fn bind_pad_axis(&mut self, pad: Option<Pad>, axis: Axis, dir: AxisDirection, name: &'a str) {
+        self.bind(Input::PadAxis(pad, axis, dir), name)
+    }
+    /// Binds a key pressed while holding exactly the modifiers `mods` to the action `name`
+    ///
+    /// This takes precedence over a binding of the key alone, so `Ctrl+S` and `S`
+    /// can be bound to different actions.
+    #[inline]
+    pub fn bind_mod_key(&mut self, mods: KeyMods, key: KeyCode, name: &'a str) {
+        self.bind(Input::ModKey(mods, key), name)
+    }
+    /// Binds holding all of `inputs` down at once to the action `name`
+    ///
+    /// The action goes down when the last of the inputs is pressed
+    /// and up when the first of them is released.
+    /// The inputs still trigger what they're bound to on their own.
+    pub fn bind_chord<N: Into<Cow<'a, str>>>(&mut self, inputs: Vec<Input>, name: N) {
+        self.chords.push(Chord {
+            inputs,
+            name: name.into(),
+            active: false,
+        });
+    }
+    /// Binds pressing `inputs` one after the other, with at most `max_gap` seconds
+    /// between each press, to the action `name`
+    ///
+    /// Like the mouse wheel, the action fires its down, press and up handlers at once.
+    /// A double-tap is the same input twice.
+    pub fn bind_sequence<N: Into<Cow<'a, str>>>(&mut self, inputs: Vec<Input>, max_gap: f32, name: N) {
+        self.sequences.push(Sequence {
+            inputs,
+            max_gap,
+            name: name.into(),
+        });
+    }
+    /// Makes `name` an axis action going from -1 when the action `negative` is pressed
+    /// to 1 when the action `positive` is pressed
+    #[inline]
+    pub fn bind_axis<N, A, B>(&mut self, name: N, negative: A, positive: B)
+    where N: Into<Cow<'a, str>>, A: Into<Cow<'a, str>>, B: Into<Cow<'a, str>> {
+        self.axis_actions.insert(name.into(), (negative.into(), positive.into()));
+    }
+    /// Makes `name` a vector action made from the axis actions `x` and `y`
+    #[inline]
+    pub fn bind_vector<N, X, Y>(&mut self, name: N, x: X, y: Y)
+    where N: Into<Cow<'a, str>>, X: Into<Cow<'a, str>>, Y: Into<Cow<'a, str>> {
+        self.vector_actions.insert(name.into(), (x.into(), y.into()));
+    }
+    /// Replaces all inputs bound to `name` with `inputs`
+    pub fn rebind<N: Into<Cow<'a, str>>>(&mut self, name: N, inputs: Vec<Input>) {
+        let name = name.into();
+        if let Some(old_inputs) = self.name_to_inputs.remove(&name) {
+            for input in old_inputs {
+                self.input_to_name.remove(&input);
+            }
+        }
+        for &input in &inputs {
+            // Take the input away from whatever action it was bound to before
+            if let Some(old_name) = self.input_to_name.insert(input, name.clone()) {
+                if let Some(old_inputs) = self.name_to_inputs.get_mut(&old_name) {
+                    old_inputs.remove(&input);
+                }
+            }
+        }
+        self.name_to_inputs.insert(name, inputs.into_iter().collect());
+    }
+    /// Gets the inputs currently bound to `name`
+    #[inline]
+    pub fn inputs_for(&self, name: &str) -> Option<&HashSet<Input>> {
+        self.name_to_inputs.get(name)
+    }
+    /// Gets the action an input is bound to
+    #[inline]
+    pub fn action_for(&self, input: Input) -> Option<&str> {
+        self.input_to_name.get(&input).map(|name| &**name)
+    }
+    /// All actions and the inputs bound to them
+    #[inline]
+    pub fn actions(&self) -> impl Iterator<Item=(&str, &HashSet<Input>)> {
+        self.name_to_inputs.iter().map(|(name, inputs)| (&**name, inputs))
+    }
+    #[inline]
+    pub(crate) fn name_for(&self, input: Input) -> Option<&Cow<'a, str>> {
+        self.input_to_name.get(&input)
+    }
+    #[inline]
+    pub(crate) fn axis_action(&self, name: &str) -> Option<(&str, &str)> {
+        self.axis_actions.get(name).map(|(negative, positive)| (&**negative, &**positive))
+    }
+    #[inline]
+    pub(crate) fn vector_action(&self, name: &str) -> Option<(&str, &str)> {
+        self.vector_actions.get(name).map(|(x, y)| (&**x, &**y))
+    }
+    /// Whether a chord bound to `name` is held down
+    #[inline]
+    pub(crate) fn chord_active(&self, name: &str) -> bool {
+        self.chords.iter().any(|chord| chord.active && chord.name == name)
+    }
+    /// The longest sequence in this context
+    #[inline]
+    pub(crate) fn longest_sequence(&self) -> usize {
+        self.sequences.iter().map(|seq| seq.inputs.len()).max().unwrap_or(0)
+    }
+    /// Activates the chords a press completes, returning their names
+    pub(crate) fn complete_chords(&mut self, input: Input, held: &HashSet<Input>) -> Vec<Cow<'a, str>> {
+        self.chords.iter_mut()
+            .filter(|chord| !chord.active && chord.inputs.iter().any(|&i| counts_as(input, i)))
+            .filter(|chord| chord.inputs.iter().all(|&i| held.iter().any(|&h| counts_as(h, i))))
+            .map(|chord| {
+                chord.active = true;
+                chord.name.clone()
+            })
+            .collect()
+    }
+    /// Deactivates the chords a release breaks, returning their names
+    pub(crate) fn break_chords(&mut self, input: Input) -> Vec<Cow<'a, str>> {
+        self.chords.iter_mut()
+            .filter(|chord| chord.active && chord.inputs.iter().any(|&i| counts_as(input, i)))
+            .map(|chord| {
+                chord.active = false;
+                chord.name.clone()
+            })
+            .collect()
+    }
+    /// The names of the sequences the latest presses in `history` finish,
+    /// where each press comes with the tick it came before and ticks are `delta` seconds long
+    pub(crate) fn finished_sequences(&self, history: &VecDeque<(Input, u64)>, delta: f32) -> Vec<Cow<'a, str>> {
+        self.sequences.iter()
+            .filter(|seq| !seq.inputs.is_empty() && seq.inputs.len() <= history.len())
+            .filter(|seq| {
+                let recent = history.iter().skip(history.len() - seq.inputs.len());
+                let mut last = None;
+                recent.zip(&seq.inputs).all(|(&(pressed, at), &wanted)| {
+                    let in_time = last.map(|last: u64| (at - last) as f32 * delta <= seq.max_gap).unwrap_or(true);
+                    last = Some(at);
+                    pressed == wanted && in_time
+                })
+            })
+            .map(|seq| seq.name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_pressed_with_modifiers_complete_chords() {
+        let mut context = InputContext::default();
+        context.bind_chord(vec![Input::Key(KeyCode::LControl), Input::Key(KeyCode::S)], "save");
+        let ctrl_s = Input::ModKey(KeyMods::CTRL, KeyCode::S);
+
+        let mut held = HashSet::new();
+        held.insert(Input::Key(KeyCode::LControl));
+        held.insert(ctrl_s);
+        assert_eq!(context.complete_chords(ctrl_s, &held), ["save"]);
+        assert_eq!(context.break_chords(ctrl_s), ["save"]);
+    }
+
+    #[test]
+    fn chords_with_modifiers_need_them() {
+        let mut context = InputContext::default();
+        let ctrl_s = Input::ModKey(KeyMods::CTRL, KeyCode::S);
+        context.bind_chord(vec![ctrl_s, Input::Key(KeyCode::D)], "split");
+
+        let mut held = HashSet::new();
+        held.insert(Input::Key(KeyCode::S));
+        held.insert(Input::Key(KeyCode::D));
+        assert!(context.complete_chords(Input::Key(KeyCode::D), &held).is_empty());
+    }
+
+    #[test]
+    fn sequences_are_timed_in_ticks() {
+        let mut context = InputContext::default();
+        let (a, b) = (Input::Key(KeyCode::A), Input::Key(KeyCode::B));
+        context.bind_sequence(vec![a, b], 0.5, "ab");
+
+        let history: VecDeque<_> = vec![(a, 10), (b, 39)].into_iter().collect();
+        assert_eq!(context.finished_sequences(&history, 1. / 60.), ["ab"]);
+        let history: VecDeque<_> = vec![(a, 10), (b, 41)].into_iter().collect();
+        assert!(context.finished_sequences(&history, 1. / 60.).is_empty());
+    }
+
+    #[test]
+    fn axes_and_vectors_take_owned_names() {
+        let mut context = InputContext::default();
+        // As if read from a file, so they don't live as long as the context
+        let names: Vec<String> = vec!["move x".into(), "move y".into()];
+        context.bind_axis(names[0].clone(), "left", "right");
+        context.bind_axis(names[1].clone(), String::from("up"), "down");
+        context.bind_vector("move", names[0].clone(), names[1].clone());
+        drop(names);
+
+        assert_eq!(context.axis_action("move x"), Some(("left", "right")));
+        assert_eq!(context.axis_action("move y"), Some(("up", "down")));
+        assert_eq!(context.vector_action("move"), Some(("move x", "move y")));
+        assert_eq!(context.axis_action("move"), None);
+    }
+}
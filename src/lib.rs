@@ -5,23 +5,22 @@
 #[macro_use]
 extern crate log;
 
+use std::borrow::Cow;
 use std::ops::{Deref, DerefMut};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 pub use ggez::{self, Context, GameError as GgezError, GameResult as GgezResult};
-pub use ggez::conf::{WindowSetup, WindowMode};
-
-use nalgebra::Matrix4;
+pub use ggez::conf::{WindowSetup, WindowMode, ModuleConf};
 
 use ggez::{
     ContextBuilder,
-    event::{run, EventHandler, KeyCode, KeyMods},
+    event::{run, EventHandler, EventsLoop, KeyCode, KeyMods, MouseButton, Axis, Button},
     graphics::{self, Rect, Color, BLACK},
-    input::keyboard,
+    input::gamepad::{self, GamepadId},
     timer,
 };
 
-use self::util::{Vector2, Point2};
+use self::util::{Point2, Vector2};
 
 pub mod util {
     use ggez::graphics::Color;
@@ -51,8 +50,24 @@ pub mod util {
 
 pub mod textures;
 pub mod object;
+pub mod bindings;
+pub mod collision;
+pub mod spatial;
+pub mod physics;
+pub mod camera;
+pub mod input;
+pub mod replay;
+pub mod headless;
+pub mod scene;
 
 use textures::Textures;
+use camera::Camera;
+use input::context::{InputContext, DEFAULT_CONTEXT};
+use input::{Input, InputEvent, WheelDirection, MousePos, Drag, DragPhase, DRAG_THRESHOLD, Pad, PadEvent, AxisDirection, past_deadzone};
+use replay::Recording;
+use collision::{Collision, CollisionTracker, Contacts};
+use scene::{Scene, SceneChange, SceneEntry, Running, Transition};
+use nalgebra::{Matrix4, Vector3};
 
 #[derive(Debug, Clone)]
 pub struct ContextConfiguration {
@@ -60,7 +75,8 @@ pub struct ContextConfiguration {
     author: &'static str,
     window_setup: WindowSetup,
     window_mode: WindowMode,
-    // TODO ggez modules (audio, gamepad)
+    modules: ModuleConf,
+    tick_rate: u32,
 }
 
 impl Default for ContextConfiguration {
@@ -70,6 +86,8 @@ impl Default for ContextConfiguration {
             author: "Falch",
             window_setup: WindowSetup::default().title("kondi"),
             window_mode: WindowMode::default().dimensions(800., 600.),
+            modules: ModuleConf::default(),
+            tick_rate: 60,
         }
     }
 }
@@ -103,20 +121,44 @@ impl ContextConfiguration {
         }
     }
 
-    pub fn run<G: Game>(self) -> Result<(), Error> {
-        // TODO maybe, add args
+    /// Sets how many ticks are run per second
+    pub fn tick_rate(self, tick_rate: u32) -> Self {
+        ContextConfiguration {
+            tick_rate,
+            .. self
+        }
+    }
+    /// Sets whether ggez should look for gamepads
+    pub fn gamepad(self, gamepad: bool) -> Self {
+        ContextConfiguration {
+            modules: self.modules.gamepad(gamepad),
+            .. self
+        }
+    }
+    /// Sets whether ggez should start its audio module
+    pub fn audio(self, audio: bool) -> Self {
+        ContextConfiguration {
+            modules: self.modules.audio(audio),
+            .. self
+        }
+    }
 
+    /// Creates the ggez context
+    fn build(self) -> GgezResult<(Context, EventsLoop)> {
         let ContextConfiguration {
             game_id,
             author,
             window_mode,
             window_setup,
+            modules,
+            tick_rate: _,
         } = self;
 
         // Create a context (the part that runs the game loop)
-        let (mut ctx, mut events) = ContextBuilder::new(game_id, author)
+        let (mut ctx, events) = ContextBuilder::new(game_id, author)
             .window_setup(window_setup)
             .window_mode(window_mode)
+            .modules(modules)
             .build()?;
 
         #[cfg(debug_assertions)]
@@ -130,21 +172,16 @@ impl ContextConfiguration {
             }
         }
 
-        
-        let mut setup = GameStateSetup::<G> {
-            state: State::new(&mut ctx)?,
-            object_set: ObjectSet::new(),
-            handlers: Handlers::new(),
-        };
-        let game = Game::setup(&mut ctx, &mut setup)?;
-        let GameStateSetup {state, object_set, handlers} = setup;
+        Ok((ctx, events))
+    }
 
-        let mut handler = GameState::<G> {
-            game,
-            handlers,
-            object_set,
-            state,
-        };
+    pub fn run<G: Game>(self) -> Result<(), Error> {
+        // TODO maybe, add args
+
+        let tick_rate = self.tick_rate;
+        let (mut ctx, mut events) = self.build()?;
+        let textures = Textures::new(&mut ctx)?;
+        let mut handler = GameState::<G>::new(&mut ctx, tick_rate, textures)?;
 
         run(&mut ctx, &mut events, &mut handler)?;
 
@@ -152,74 +189,669 @@ impl ContextConfiguration {
     }
 }
 
-use object::ObjectSet;
+use object::{Object, ObjectId, ObjectSet, commands::Commands};
 
 pub type KeyHandler<G> = Box<dyn FnMut(&mut Context, &mut G, &mut State, &mut ObjectSet) -> GgezResult>;
+pub type MouseHandler<G> = Box<dyn FnMut(&mut Context, &mut G, &mut State, &mut ObjectSet, MousePos) -> GgezResult>;
+pub type DragHandler<G> = Box<dyn FnMut(&mut Context, &mut G, &mut State, &mut ObjectSet, &Drag) -> GgezResult>;
+
+/// The actions of one input context that are held down or just changed
+#[derive(Debug, Default)]
+struct ActionStates<'a> {
+    /// How many inputs are holding each action down
+    counts: HashMap<Cow<'a, str>, u32>,
+    just_pressed: HashSet<Cow<'a, str>>,
+    /// Actions released this tick and how long they were held for
+    just_released: HashMap<Cow<'a, str>, f32>,
+    /// The tick each held action was pressed on
+    pressed_at: HashMap<Cow<'a, str>, u64>,
+}
 
 #[derive(Debug)]
 pub struct State<'a> {
     pub textures: Textures,
-    pub offset: Vector2,
+    pub camera: Camera,
     width: f32,
     height: f32,
     pub background: Color,
+    /// Changes to the `ObjectSet` to be made after the current update or handler
+    pub commands: Commands,
+    /// How far a gamepad axis has to be pushed before it counts, from 0 to 1
+    pub deadzone: f32,
 
     error: Option<GgezError>,
-    key_to_name: HashMap<KeyCode, &'a str>,
-    name_to_keys: HashMap<&'a str, HashSet<KeyCode>>
+    pads: Vec<Pad>,
+    pad_buttons: HashSet<(Pad, Button)>,
+    pad_axes: HashMap<(Pad, Axis), f32>,
+    pad_events: Vec<PadEvent>,
+    /// Every input context by name, including the default one
+    contexts: HashMap<&'a str, InputContext<'a>>,
+    /// The names of the active contexts, the last is on top
+    context_stack: Vec<&'a str>,
+    /// The context and action each held input was sent to when pressed
+    pressed_inputs: HashMap<Input, (&'a str, Cow<'a, str>)>,
+    /// The inputs held down right now according to the events so far
+    held: HashSet<Input>,
+    /// What each held key was pressed as, with or without the modifiers held then
+    keys_down: HashMap<KeyCode, Input>,
+    /// Where the mouse is on the screen
+    mouse: Point2,
+    /// The recording being made and the tick it started on
+    recording: Option<(Recording, u64)>,
+    playback: Option<Playback>,
+    /// The latest presses and the ticks they came before, for matching sequences
+    history: VecDeque<(Input, u64)>,
+
+    tick_rate: u32,
+    tick: u64,
+    paused: bool,
+    /// Whether to run one tick on the next update even if paused
+    step: bool,
+    /// How fast the game runs, 0.5 is half speed
+    pub time_scale: f32,
+    /// Scaled time not yet used up by ticks in seconds
+    accumulator: f32,
+    unscaled_delta: f32,
+    /// The state of the actions of each input context,
+    /// kept apart so contexts with actions of the same name don't mix them up
+    actions: HashMap<&'a str, ActionStates<'a>>,
+    /// Actions pressed (`true`) and released (`false`) since the last tick and their contexts
+    pending_actions: Vec<(&'a str, Cow<'a, str>, bool)>,
+    /// Changes to the scene stack waiting to be made, in order
+    scene_changes: VecDeque<(SceneChange, Transition)>,
 }
 
 impl<'a> State<'a> {
-    fn new(ctx: &mut Context) -> GgezResult<Self> {
+    fn new(ctx: &mut Context, object_set: &ObjectSet, tick_rate: u32, textures: Textures) -> Self {
         let Rect {w: width, h: height, ..} = graphics::screen_coordinates(ctx);
-        Ok(State {
-            textures: Textures::new(ctx)?,
-            offset: Vector2::new(0., 0.),
+        Self::with_size(width, height, object_set, tick_rate, textures)
+    }
+    /// Makes the state for a screen of the given size without asking ggez
+    fn with_size(width: f32, height: f32, object_set: &ObjectSet, tick_rate: u32, textures: Textures) -> Self {
+        State {
+            textures,
+            // Start out with the world lined up with the screen
+            camera: Camera::new(Point2::new(width / 2., height / 2.)),
             width,
             height,
             background: BLACK,
+            commands: object_set.commands(),
+            deadzone: 0.2,
             error: None,
-            key_to_name: HashMap::new(),
-            name_to_keys: HashMap::new(),
-        })
+            pads: Vec::new(),
+            pad_buttons: HashSet::new(),
+            pad_axes: HashMap::new(),
+            pad_events: Vec::new(),
+            contexts: vec![(DEFAULT_CONTEXT, InputContext::default())].into_iter().collect(),
+            context_stack: vec![DEFAULT_CONTEXT],
+            pressed_inputs: HashMap::new(),
+            held: HashSet::new(),
+            keys_down: HashMap::new(),
+            mouse: Point2::new(0., 0.),
+            recording: None,
+            playback: None,
+            history: VecDeque::new(),
+            tick_rate,
+            tick: 0,
+            paused: false,
+            step: false,
+            time_scale: 1.,
+            accumulator: 0.,
+            unscaled_delta: 0.,
+            actions: HashMap::new(),
+            pending_actions: Vec::new(),
+            scene_changes: VecDeque::new(),
+        }
     }
-    /// Sets the offset so that the given point will be centered on the screen
+    /// Moves the camera so that the given point will be centered on the screen
     #[inline]
     pub fn focus_on(&mut self, p: Point2) {
-        self.offset = -p.coords + 0.5 * Vector2::new(self.width, self.height);
+        self.camera.pos = p;
+    }
+    /// Where the origin of the world is drawn on the screen, which is what the offset used to be
+    #[deprecated(note = "the offset is now worked out from `camera`, use `camera.pos` or `world_to_screen`")]
+    #[inline]
+    pub fn offset(&self) -> Vector2 {
+        self.world_to_screen(Point2::origin()).coords
+    }
+    /// Moves the camera so the origin of the world is drawn at `offset` on the screen,
+    /// as setting the offset used to
+    ///
+    /// This only matches the old offset while the camera isn't zoomed or rotated.
+    #[deprecated(note = "the offset is now worked out from `camera`, use `focus_on` or `camera.pos`")]
+    #[inline]
+    pub fn set_offset(&mut self, offset: Vector2) {
+        self.camera.pos = Point2::from(0.5 * Vector2::new(self.width, self.height) - offset);
     }
     #[inline(always)]
     pub fn dims(&self) -> (f32, f32) {
         (self.width, self.height)
     }
+    /// Converts a point on the screen, like the mouse position, to the world
+    #[inline]
+    pub fn screen_to_world(&self, p: Point2) -> Point2 {
+        self.camera.screen_to_world(p, self.width, self.height)
+    }
+    /// Converts a point in the world to where it's drawn on the screen
+    #[inline]
+    pub fn world_to_screen(&self, p: Point2) -> Point2 {
+        self.camera.world_to_screen(p, self.width, self.height)
+    }
+    /// Where the mouse is in the world
+    #[inline]
+    pub fn mouse_world_pos(&self) -> Point2 {
+        self.mouse_pos().world
+    }
+    /// Where the mouse is on the screen and in the world
     #[inline]
-    pub fn bind_key(&mut self, key: KeyCode, name: &'a str) {
-        self.key_to_name.insert(key, name);
-        self.name_to_keys.entry(name).or_insert_with(HashSet::new).insert(key);
+    pub fn mouse_pos(&self) -> MousePos {
+        self.mouse_pos_at(self.mouse)
     }
+    /// Makes a mouse position from a point on the screen
     #[inline]
-    pub fn bind_keys(&mut self, name: &'a str, keys: Vec<KeyCode>) {
-        for &key in &keys {
-            self.key_to_name.insert(key, name);
+    pub fn mouse_pos_at(&self, screen: Point2) -> MousePos {
+        MousePos {
+            screen,
+            world: self.screen_to_world(screen),
+        }
+    }
+    /// Finds the object drawn on top under the mouse
+    #[inline]
+    pub fn object_under_mouse(&self, object_set: &ObjectSet) -> Option<ObjectId<dyn Object>> {
+        object_set.topmost_at(self.mouse_world_pos())
+    }
+    /// Gets the input context called `name`, making it if it doesn't exist
+    pub fn context_mut(&mut self, name: &'a str) -> &mut InputContext<'a> {
+        self.contexts.entry(name).or_default()
+    }
+    /// Gets the input context called `name`
+    #[inline]
+    pub fn context(&self, name: &str) -> Option<&InputContext<'a>> {
+        self.contexts.get(name)
+    }
+    /// Puts the input context `name` on top of the others, making it if it doesn't exist
+    pub fn push_context(&mut self, name: &'a str) {
+        self.context_mut(name);
+        self.context_stack.push(name);
+    }
+    /// Takes the top input context off the stack
+    ///
+    /// The default context is never popped.
+    pub fn pop_context(&mut self) -> Option<&'a str> {
+        if self.context_stack.len() > 1 {
+            self.context_stack.pop()
+        } else {
+            None
+        }
+    }
+    /// Takes the highest `name` off the stack wherever it is
+    ///
+    /// The default context at the bottom is never taken off.
+    fn remove_context(&mut self, name: &str) {
+        let stack = &mut self.context_stack;
+        if let Some(i) = stack.iter().rposition(|&context| context == name).filter(|&i| i > 0) {
+            stack.remove(i);
         }
-        self.name_to_keys.entry(name).or_insert_with(HashSet::new).extend(keys);
     }
+    /// The name of the input context on top
     #[inline]
-    pub fn is_down(&self, ctx: &Context, name: &str) -> bool {
-        if let Some(keys_for_name) = self.name_to_keys.get(name) {
-            keyboard::pressed_keys(&ctx)
-                .intersection(keys_for_name)
-                .any(|_| true)
+    pub fn top_context(&self) -> &'a str {
+        self.context_stack[self.context_stack.len() - 1]
+    }
+    /// The contexts inputs can reach from the top down,
+    /// stopping at the first that doesn't pass inputs through
+    fn reachable_contexts(&self) -> Vec<(&'a str, &InputContext<'a>)> {
+        let mut reachable = Vec::new();
+        for &name in self.context_stack.iter().rev() {
+            let context = &self.contexts[name];
+            reachable.push((name, context));
+            if !context.pass_through {
+                break;
+            }
+        }
+        reachable
+    }
+    /// The context and action an input goes to if pressed now
+    fn route(&self, input: Input) -> Option<(&'a str, Cow<'a, str>)> {
+        self.reachable_contexts()
+            .into_iter()
+            .find_map(|(context_name, context)| context.name_for(input).map(|name| (context_name, name.clone())))
+    }
+    /// The input a key press counts as with the modifiers held
+    fn resolve_key(&self, key: KeyCode, mods: KeyMods) -> Input {
+        let with_mods = Input::ModKey(mods, key);
+        if !mods.is_empty() && self.route(with_mods).is_some() {
+            with_mods
+        } else {
+            Input::Key(key)
+        }
+    }
+    /// Records a press, returning the chords it completes and the sequences it finishes
+    /// along with the contexts they're in
+    #[allow(clippy::type_complexity)]
+    fn pressed(&mut self, input: Input) -> (Vec<(&'a str, Cow<'a, str>)>, Vec<(&'a str, Cow<'a, str>)>) {
+        self.held.insert(input);
+
+        let reachable: Vec<_> = self.reachable_contexts().into_iter().map(|(name, _)| name).collect();
+        let mut chords = Vec::new();
+        for &context_name in &reachable {
+            let context = self.contexts.get_mut(context_name).unwrap();
+            chords.extend(context.complete_chords(input, &self.held).into_iter().map(|name| (context_name, name)));
+        }
+
+        // Timed in ticks rather than real time so replays finish the same sequences
+        self.history.push_back((input, self.tick));
+        let longest = self.contexts.values().map(InputContext::longest_sequence).max().unwrap_or(0);
+        while self.history.len() > longest {
+            self.history.pop_front();
+        }
+        let mut sequences = Vec::new();
+        for &context_name in &reachable {
+            let finished = self.contexts[context_name].finished_sequences(&self.history, self.delta());
+            sequences.extend(finished.into_iter().map(|name| (context_name, name)));
+        }
+        // Start over so the same presses can't finish a sequence twice
+        if !sequences.is_empty() {
+            self.history.clear();
+        }
+
+        (chords, sequences)
+    }
+    /// Records a release, returning the chords it breaks in every context
+    fn released(&mut self, input: Input) -> Vec<(&'a str, Cow<'a, str>)> {
+        self.held.remove(&input);
+        let mut context_names: Vec<_> = self.contexts.keys().copied().collect();
+        context_names.sort();
+        let mut chords = Vec::new();
+        for context_name in context_names {
+            let context = self.contexts.get_mut(context_name).unwrap();
+            chords.extend(context.break_chords(input).into_iter().map(|name| (context_name, name)));
+        }
+        chords
+    }
+    /// Whether an input is held down according to the events so far
+    ///
+    /// This doesn't ask ggez, so that played back events count the same as real ones.
+    fn input_down(&self, input: Input) -> bool {
+        match input {
+            // A key pressed with modifiers is only down as what the press was resolved to
+            Input::Key(key) | Input::ModKey(_, key) => self.keys_down.get(&key) == Some(&input),
+            Input::Mouse(button) => self.held.contains(&Input::Mouse(button)),
+            Input::Wheel(_) => false,
+            Input::PadButton(pad, button) => self.pad_button_down(pad, button),
+            Input::PadAxis(pad, axis, dir) => self.pad_axis_pushed(pad, axis, dir),
+        }
+    }
+    /// The inputs bound to `name` in the contexts that can be reached
+    /// that aren't taken by a context above
+    fn reachable_inputs(&self, name: &str) -> Vec<Input> {
+        let reachable = self.reachable_contexts();
+        let mut inputs = Vec::new();
+        for (i, &(_, context)) in reachable.iter().enumerate() {
+            if let Some(inputs_for_name) = context.inputs_for(name) {
+                inputs.extend(inputs_for_name.iter()
+                    .filter(|&&input| reachable[..i].iter().all(|(_, above)| above.action_for(input).is_none())));
+            }
+        }
+        inputs
+    }
+    /// Whether the action `name` is held down in any of the contexts that inputs can reach
+    pub fn is_down(&self, name: &str) -> bool {
+        self.reachable_contexts().iter().any(|(_, context)| context.chord_active(name))
+            || self.reachable_inputs(name).into_iter().any(|input| self.input_down(input))
+    }
+    /// How far the action `name` is pressed from 0 to 1
+    ///
+    /// Keys and buttons count as fully pressed while held down,
+    /// gamepad axes count by how far they're pushed past the deadzone.
+    pub fn action_value(&self, name: &str) -> f32 {
+        if self.reachable_contexts().iter().any(|(_, context)| context.chord_active(name)) {
+            return 1.;
+        }
+        self.reachable_inputs(name).into_iter().map(|input| match input {
+            Input::PadAxis(pad, axis, dir) => {
+                let sign = match dir {
+                    AxisDirection::Negative => -1.,
+                    AxisDirection::Positive => 1.,
+                };
+                self.pads.iter()
+                    .filter(|&&p| pad.map(|pad| pad == p).unwrap_or(true))
+                    .map(|&p| sign * self.pad_axis(p, axis))
+                    .fold(0., f32::max)
+            }
+            input => if self.input_down(input) { 1. } else { 0. },
+        }).fold(0., f32::max)
+    }
+    /// The value of the axis action `name` from -1 to 1
+    pub fn axis(&self, name: &str) -> f32 {
+        let axis = self.reachable_contexts().into_iter().find_map(|(_, context)| context.axis_action(name));
+        match axis {
+            Some((negative, positive)) => self.action_value(positive) - self.action_value(negative),
+            None => 0.,
+        }
+    }
+    /// The value of the vector action `name`, at most 1 long
+    ///
+    /// Pressing two directions at once, like up and right, gives a vector of length 1
+    /// so that moving diagonally isn't faster.
+    pub fn vector(&self, name: &str) -> Vector2 {
+        let vector = self.reachable_contexts().into_iter().find_map(|(_, context)| context.vector_action(name));
+        let v = match vector {
+            Some((x, y)) => Vector2::new(self.axis(x), self.axis(y)),
+            None => return Vector2::zeros(),
+        };
+        let len = v.norm();
+        if len > 1. {
+            v / len
         } else {
-            false
+            v
+        }
+    }
+    /// Starts recording input events, throwing away any recording already going on
+    #[inline]
+    pub fn start_recording(&mut self) {
+        self.recording = Some((Recording::new(), self.tick));
+    }
+    /// Stops recording and gives back what was recorded
+    #[inline]
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take().map(|(recording, _)| recording)
+    }
+    #[inline]
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+    /// Plays back a recording from the next tick, ignoring real input until it's done
+    ///
+    /// To reproduce a run, play it back from the same state the recording was started in,
+    /// like in `Game::setup`.
+    #[inline]
+    pub fn play(&mut self, recording: Recording) {
+        self.playback = Some(Playback {
+            recording,
+            next: 0,
+            start: self.tick,
+        });
+    }
+    /// Stops playing back, going back to real input
+    #[inline]
+    pub fn stop_playing(&mut self) {
+        self.playback = None;
+    }
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+    fn record(&mut self, event: InputEvent) {
+        if let Some((recording, start)) = &mut self.recording {
+            recording.events.push((self.tick - *start, event));
         }
     }
+    /// Takes the next played back event if it came before the coming tick
+    fn next_played_event(&mut self) -> Option<InputEvent> {
+        let playback = self.playback.as_mut()?;
+        match playback.recording.events.get(playback.next) {
+            Some(&(tick, event)) if playback.start + tick <= self.tick => {
+                playback.next += 1;
+                Some(event)
+            }
+            Some(_) => None,
+            None => {
+                self.playback = None;
+                None
+            }
+        }
+    }
+    /// How many ticks are run per second
+    #[inline]
+    pub fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+    /// How long a tick is in seconds
+    #[inline]
+    pub fn delta(&self) -> f32 {
+        1. / self.tick_rate as f32
+    }
+    /// How many ticks have been run
+    #[inline]
+    pub fn tick_count(&self) -> u64 {
+        self.tick
+    }
+    /// Stops running ticks, while still drawing and handling input
+    #[inline]
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+    #[inline]
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+    #[inline]
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    /// Runs exactly one tick on the next update, even while paused
+    #[inline]
+    pub fn step(&mut self) {
+        self.step = true;
+    }
+    /// The real time since the last update in seconds, not affected by pausing or the time scale
+    #[inline]
+    pub fn unscaled_delta(&self) -> f32 {
+        self.unscaled_delta
+    }
+    /// Puts a scene on top of the scene stack at the start of the next tick
+    ///
+    /// Changes wait for the transition before them to finish.
+    #[inline]
+    pub fn push_scene<S: Scene + 'static>(&mut self, scene: S, transition: Transition) {
+        self.scene_changes.push_back((SceneChange::Push(Box::new(scene)), transition));
+    }
+    /// Takes the top scene off the scene stack at the start of the next tick
+    ///
+    /// The `Game` at the bottom is never popped.
+    #[inline]
+    pub fn pop_scene(&mut self, transition: Transition) {
+        self.scene_changes.push_back((SceneChange::Pop, transition));
+    }
+    /// Swaps the top scene for another at the start of the next tick
+    #[inline]
+    pub fn replace_scene<S: Scene + 'static>(&mut self, scene: S, transition: Transition) {
+        self.scene_changes.push_back((SceneChange::Replace(Box::new(scene)), transition));
+    }
+    /// Whether the action `name` was pressed since the tick before this one in any context
+    ///
+    /// This only changes once per tick, so every press is seen by exactly one tick
+    /// no matter how many frames are drawn in between.
+    #[inline]
+    pub fn just_pressed(&self, name: &str) -> bool {
+        self.actions.values().any(|actions| actions.just_pressed.contains(name))
+    }
+    /// Whether the action `name` of the input context `context` was pressed since the tick before this one
+    #[inline]
+    pub fn just_pressed_in(&self, context: &str, name: &str) -> bool {
+        self.actions.get(context).map(|actions| actions.just_pressed.contains(name)).unwrap_or(false)
+    }
+    /// Whether the action `name` was released since the tick before this one in any context
+    #[inline]
+    pub fn just_released(&self, name: &str) -> bool {
+        self.actions.values().any(|actions| actions.just_released.contains_key(name))
+    }
+    /// Whether the action `name` of the input context `context` was released since the tick before this one
+    #[inline]
+    pub fn just_released_in(&self, context: &str, name: &str) -> bool {
+        self.released_after_in(context, name).is_some()
+    }
+    /// How long the action `name` has been held down in seconds, counted in ticks,
+    /// the longest of any context
+    ///
+    /// This is 0 on the tick it's pressed and if it isn't held.
+    pub fn held_for(&self, name: &str) -> f32 {
+        self.actions.keys()
+            .map(|context| self.held_for_in(context, name))
+            .fold(0., f32::max)
+    }
+    /// How long the action `name` of the input context `context` has been held down in seconds
+    pub fn held_for_in(&self, context: &str, name: &str) -> f32 {
+        self.actions.get(context)
+            .and_then(|actions| actions.pressed_at.get(name))
+            .map(|&at| (self.tick - at) as f32 * self.delta())
+            .unwrap_or(0.)
+    }
+    /// How long the action `name` was held down for, on the tick it's released in any context
+    #[inline]
+    pub fn released_after(&self, name: &str) -> Option<f32> {
+        self.actions.values()
+            .filter_map(|actions| actions.just_released.get(name).copied())
+            .fold(None, |longest: Option<f32>, held| Some(longest.map_or(held, |longest| longest.max(held))))
+    }
+    /// How long the action `name` of the input context `context` was held down for, on the tick it's released
+    #[inline]
+    pub fn released_after_in(&self, context: &str, name: &str) -> Option<f32> {
+        self.actions.get(context).and_then(|actions| actions.just_released.get(name).copied())
+    }
+    fn action_down(&mut self, context: &'a str, name: &str) {
+        let counts = &mut self.actions.entry(context).or_default().counts;
+        match counts.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                counts.insert(Cow::Owned(name.to_owned()), 1);
+                self.pending_actions.push((context, Cow::Owned(name.to_owned()), true));
+            }
+        }
+    }
+    fn action_up(&mut self, context: &'a str, name: &str) {
+        let counts = match self.actions.get_mut(context) {
+            Some(actions) => &mut actions.counts,
+            None => return,
+        };
+        if let Some(count) = counts.get_mut(name) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(name);
+                self.pending_actions.push((context, Cow::Owned(name.to_owned()), false));
+            }
+        }
+    }
+    /// Adds `frame_time` scaled by `time_scale` to the time not yet used up by ticks
+    /// and takes out how many ticks to run for it, counting the step if there is one
+    fn take_ticks(&mut self, frame_time: f32) -> u32 {
+        if !self.paused {
+            self.accumulator += frame_time * self.time_scale;
+        }
+        let delta = self.delta();
+        let mut ticks = 0;
+        while self.accumulator >= delta {
+            if ticks == MAX_TICKS_PER_UPDATE {
+                // Give up catching up rather than falling further and further behind
+                self.accumulator %= delta;
+                break;
+            }
+            self.accumulator -= delta;
+            ticks += 1;
+        }
+        if std::mem::replace(&mut self.step, false) {
+            ticks += 1;
+        }
+        ticks
+    }
+    /// Moves the presses and releases since the last tick over to this tick
+    fn begin_tick(&mut self) {
+        self.tick += 1;
+        for actions in self.actions.values_mut() {
+            actions.just_pressed.clear();
+            actions.just_released.clear();
+        }
+        let delta = self.delta();
+        for (context, name, down) in self.pending_actions.drain(..) {
+            let actions = self.actions.entry(context).or_default();
+            if down {
+                actions.pressed_at.insert(name.clone(), self.tick);
+                actions.just_pressed.insert(name);
+            } else {
+                let at = actions.pressed_at.remove(&name).unwrap_or(self.tick);
+                let held_for = (self.tick - at) as f32 * delta;
+                actions.just_released.insert(name, held_for);
+            }
+        }
+    }
+    /// The gamepads currently connected, in the order they connected
+    #[inline]
+    pub fn pads(&self) -> &[Pad] {
+        &self.pads
+    }
+    /// Whether a button is held on the given gamepad, or on any gamepad if `None`
+    pub fn pad_button_down(&self, pad: Option<Pad>, button: Button) -> bool {
+        match pad {
+            Some(pad) => self.pad_buttons.contains(&(pad, button)),
+            None => self.pad_buttons.iter().any(|&(_, b)| b == button),
+        }
+    }
+    /// The value of an axis on a gamepad from -1 to 1
+    ///
+    /// Values inside the deadzone are 0 and the rest is scaled to still reach 1.
+    pub fn pad_axis(&self, pad: Pad, axis: Axis) -> f32 {
+        let value = self.pad_axes.get(&(pad, axis)).copied().unwrap_or(0.);
+        past_deadzone(value, self.deadzone)
+    }
+    /// Whether an axis is pushed past the deadzone on the given gamepad, or on any gamepad if `None`
+    pub fn pad_axis_pushed(&self, pad: Option<Pad>, axis: Axis, dir: AxisDirection) -> bool {
+        self.pad_axes.iter()
+            .filter(|&(&(p, a), _)| a == axis && pad.map(|pad| pad == p).unwrap_or(true))
+            .any(|(_, &value)| dir.pushed(value, self.deadzone))
+    }
+    /// Queues a gamepad event to be handled at the start of the next update
+    /// as if it came from a real gamepad
+    #[inline]
+    pub fn inject_pad_event(&mut self, event: PadEvent) {
+        self.pad_events.push(event);
+    }
+}
+
+/// A recording being played back
+#[derive(Debug)]
+struct Playback {
+    recording: Recording,
+    /// The index of the next event to play
+    next: usize,
+    /// The tick the playback started on
+    start: u64,
+}
+
+/// Binding on the `State` binds in the default input context
+impl<'a> Deref for State<'a> {
+    type Target = InputContext<'a>;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.contexts[DEFAULT_CONTEXT]
+    }
+}
+impl<'a> DerefMut for State<'a> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.context_mut(DEFAULT_CONTEXT)
+    }
 }
 
 struct Handlers<'a, G: Game> {
     key_up_handlers: HashMap<&'a str, KeyHandler<G>>,
     key_down_handlers: HashMap<&'a str, KeyHandler<G>>,
     key_press_handlers: HashMap<&'a str, KeyHandler<G>>,
+    mouse_down_handlers: HashMap<&'a str, MouseHandler<G>>,
+    mouse_up_handlers: HashMap<&'a str, MouseHandler<G>>,
+    drag_handlers: HashMap<&'a str, DragHandler<G>>,
+    mouse_motion_handler: Option<MouseHandler<G>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum KeyEvent {
+    Up,
+    Down,
+    Press,
 }
 
 impl<'a, G: Game> Handlers<'a, G> {
@@ -229,6 +861,10 @@ impl<'a, G: Game> Handlers<'a, G> {
             key_up_handlers: HashMap::new(),
             key_down_handlers: HashMap::new(),
             key_press_handlers: HashMap::new(),
+            mouse_down_handlers: HashMap::new(),
+            mouse_up_handlers: HashMap::new(),
+            drag_handlers: HashMap::new(),
+            mouse_motion_handler: None,
         }
     }
 }
@@ -236,10 +872,57 @@ impl<'a, G: Game> Handlers<'a, G> {
 pub struct GameStateSetup<'a, G: Game> {
     pub state: State<'a>,
     pub object_set: ObjectSet,
-    handlers: Handlers<'a, G>,
+    /// The handlers of each input context
+    handlers: HashMap<&'a str, Handlers<'a, G>>,
 }
 
 impl<'a, G: Game> GameStateSetup<'a, G> {
+    /// Sets up the bindings and handlers of the input context `name`
+    pub fn context(&mut self, name: &'a str) -> ContextSetup<'_, 'a, G> {
+        ContextSetup {
+            bindings: self.state.context_mut(name),
+            handlers: self.handlers.entry(name).or_insert_with(Handlers::new),
+        }
+    }
+    #[inline]
+    pub fn add_key_up_handler(&mut self, name: &'a str, handler: KeyHandler<G>) {
+        self.context(DEFAULT_CONTEXT).add_key_up_handler(name, handler)
+    }
+    #[inline]
+    pub fn add_key_down_handler(&mut self, name: &'a str, handler: KeyHandler<G>) {
+        self.context(DEFAULT_CONTEXT).add_key_down_handler(name, handler)
+    }
+    #[inline]
+    pub fn add_key_press_handler(&mut self, name: &'a str, handler: KeyHandler<G>) {
+        self.context(DEFAULT_CONTEXT).add_key_press_handler(name, handler)
+    }
+    #[inline]
+    pub fn add_mouse_down_handler(&mut self, name: &'a str, handler: MouseHandler<G>) {
+        self.context(DEFAULT_CONTEXT).add_mouse_down_handler(name, handler)
+    }
+    #[inline]
+    pub fn add_mouse_up_handler(&mut self, name: &'a str, handler: MouseHandler<G>) {
+        self.context(DEFAULT_CONTEXT).add_mouse_up_handler(name, handler)
+    }
+    #[inline]
+    pub fn add_drag_handler(&mut self, name: &'a str, handler: DragHandler<G>) {
+        self.context(DEFAULT_CONTEXT).add_drag_handler(name, handler)
+    }
+    #[inline]
+    pub fn set_mouse_motion_handler(&mut self, handler: MouseHandler<G>) {
+        self.context(DEFAULT_CONTEXT).set_mouse_motion_handler(handler)
+    }
+}
+
+/// The bindings and handlers of one input context during setup
+///
+/// Binding on it binds in the context.
+pub struct ContextSetup<'s, 'a, G: Game> {
+    bindings: &'s mut InputContext<'a>,
+    handlers: &'s mut Handlers<'a, G>,
+}
+
+impl<'a, G: Game> ContextSetup<'_, 'a, G> {
     #[inline]
     pub fn add_key_up_handler(&mut self, name: &'a str, handler: KeyHandler<G>) {
         self.handlers.key_up_handlers.insert(name, handler);
@@ -252,6 +935,44 @@ impl<'a, G: Game> GameStateSetup<'a, G> {
     pub fn add_key_press_handler(&mut self, name: &'a str, handler: KeyHandler<G>) {
         self.handlers.key_press_handlers.insert(name, handler);
     }
+    /// Adds a handler for when a mouse button bound to `name` is pressed
+    ///
+    /// This is run after the key down and key press handlers of the action.
+    #[inline]
+    pub fn add_mouse_down_handler(&mut self, name: &'a str, handler: MouseHandler<G>) {
+        self.handlers.mouse_down_handlers.insert(name, handler);
+    }
+    /// Adds a handler for when a mouse button bound to `name` is released
+    ///
+    /// This is run after the key up handler of the action.
+    #[inline]
+    pub fn add_mouse_up_handler(&mut self, name: &'a str, handler: MouseHandler<G>) {
+        self.handlers.mouse_up_handlers.insert(name, handler);
+    }
+    /// Adds a handler for dragging with a mouse button bound to `name`
+    #[inline]
+    pub fn add_drag_handler(&mut self, name: &'a str, handler: DragHandler<G>) {
+        self.handlers.drag_handlers.insert(name, handler);
+    }
+    /// Sets the handler run whenever the mouse moves while this context can be reached
+    #[inline]
+    pub fn set_mouse_motion_handler(&mut self, handler: MouseHandler<G>) {
+        self.handlers.mouse_motion_handler = Some(handler);
+    }
+}
+
+impl<'a, G: Game> Deref for ContextSetup<'_, 'a, G> {
+    type Target = InputContext<'a>;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        self.bindings
+    }
+}
+impl<'a, G: Game> DerefMut for ContextSetup<'_, 'a, G> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.bindings
+    }
 }
 
 impl<'a, G: Game> Deref for GameStateSetup<'a, G> {
@@ -271,83 +992,696 @@ impl<'a, G: Game> DerefMut for GameStateSetup<'a, G> {
 struct GameState<'a, G: Game> {
     state: State<'a>,
     pub object_set: ObjectSet,
-    handlers: Handlers<'a, G>,
+    handlers: HashMap<&'a str, Handlers<'a, G>>,
     game: G,
+    collisions: CollisionTracker,
+    drags: HashMap<MouseButton, DragState>,
+    /// The numbers given to the real gamepads
+    pad_ids: HashMap<GamepadId, Pad>,
+    /// The scenes on top of the game, the last is on top
+    scenes: Vec<SceneEntry>,
+    transition: Option<Running>,
 }
 
-const DESIRED_FPS: u32 = 60;
+/// The most ticks run in one update when the game falls behind
+const MAX_TICKS_PER_UPDATE: u32 = 10;
 
-pub(crate) const DELTA: f32 = 1. / DESIRED_FPS as f32;
+/// A mouse button being held down that may turn into a drag
+#[derive(Debug, Clone, Copy)]
+struct DragState {
+    start: MousePos,
+    last: Point2,
+    dragging: bool,
+}
 
-impl<G: Game> EventHandler for GameState<'_, G> {
-    fn update(&mut self, ctx: &mut Context) -> GgezResult {
+impl<'a, G: Game> GameState<'a, G> {
+    /// Sets up the game
+    fn new(ctx: &mut Context, tick_rate: u32, textures: Textures) -> GgezResult<Self> {
+        let object_set = ObjectSet::new();
+        let mut setup = GameStateSetup::<G> {
+            state: State::new(ctx, &object_set, tick_rate, textures),
+            object_set,
+            handlers: HashMap::new(),
+        };
+        let game = Game::setup(ctx, &mut setup)?;
+        let GameStateSetup {mut state, mut object_set, handlers} = setup;
+        object_set.apply(&mut state.commands);
+        object_set.rebuild_spatial_index();
+
+        Ok(GameState {
+            game,
+            handlers,
+            object_set,
+            state,
+            collisions: CollisionTracker::default(),
+            drags: HashMap::new(),
+            pad_ids: HashMap::new(),
+            scenes: Vec::new(),
+            transition: None,
+        })
+    }
+    /// Runs what happens once per update before the ticks
+    fn before_ticks(&mut self, ctx: &mut Context) -> GgezResult {
         if let Some(error) = self.state.error.take() {
             return Err(error);
         }
+        if !self.pad_ids.is_empty() && !self.state.is_playing() {
+            self.check_pads(ctx);
+        }
+        for event in std::mem::take(&mut self.state.pad_events) {
+            self.live_event(ctx, InputEvent::Pad(event));
+        }
         self.game.logic(ctx, &mut self.state, &mut self.object_set)?;
+        self.object_set.apply(&mut self.state.commands);
+        self.object_set.run_lifecycle(ctx, &mut self.state);
+        for entry in &mut self.scenes {
+            entry.run_lifecycle(ctx, &mut self.state);
+        }
+        Ok(())
+    }
+    /// Runs one update `frame_time` seconds after the last,
+    /// with as many ticks as the time scaled by `time_scale` makes up for
+    fn update_by(&mut self, ctx: &mut Context, frame_time: f32) -> GgezResult {
+        self.before_ticks(ctx)?;
+
+        self.state.unscaled_delta = frame_time;
+        let state = &mut self.state;
+        self.object_set.update_each(|obj, objects| obj.update_unscaled(ctx, state, objects, frame_time));
+        self.object_set.apply(&mut self.state.commands);
+        self.game.update_unscaled(ctx, &mut self.state, &mut self.object_set, frame_time)?;
+        self.object_set.apply(&mut self.state.commands);
+        for entry in &mut self.scenes {
+            entry.update_unscaled(ctx, &mut self.state, frame_time)?;
+        }
+        // Scenes change on ticks, but a paused game should still be able to bring up a menu
+        if self.state.paused && !self.state.step {
+            self.advance_scenes(ctx, frame_time)?;
+        }
+
+        for _ in 0..self.state.take_ticks(frame_time) {
+            self.tick(ctx)?;
+        }
+        Ok(())
+    }
+    /// Moves the game forward one tick
+    fn tick(&mut self, ctx: &mut Context) -> GgezResult {
+        while let Some(event) = self.state.next_played_event() {
+            self.input_event(ctx, event);
+        }
+        let tick_time = self.state.delta();
+        self.advance_scenes(ctx, tick_time)?;
+        self.state.begin_tick();
+        let delta = self.state.delta();
+        let lowest = scene::lowest_layer(&self.scenes, |scene| scene.update_below());
+        if lowest == 0 {
+            self.object_set.run_lifecycle(ctx, &mut self.state);
+            let state = &mut self.state;
+            self.object_set.update_each(|obj, objects| obj.update(ctx, state, objects, delta));
+            self.object_set.apply(&mut self.state.commands);
+            let contacts = physics::step(&mut self.object_set, delta);
+            self.object_set.rebuild_spatial_index();
+            self.handle_collisions(ctx, contacts);
+            self.game.tick(ctx, &mut self.state, &mut self.object_set, delta)?;
+            self.object_set.apply(&mut self.state.commands);
+        }
+        // Layer 0 is the game, so the scene of layer n is at n - 1
+        for entry in &mut self.scenes[lowest.saturating_sub(1)..] {
+            entry.tick(ctx, &mut self.state, delta)?;
+        }
+
+        let State { camera, width, height, .. } = &mut self.state;
+        camera.update(&self.object_set, *width, *height, delta);
 
-        while timer::check_update_time(ctx, DESIRED_FPS) {
-            for obj in self.object_set.iter_mut() {
-                obj.update(ctx, &mut self.state, DELTA);
+        let view = camera.view(*width, *height);
+        if lowest == 0 {
+            self.object_set.run_view_hooks(ctx, &mut self.state, view);
+        }
+        for entry in &mut self.scenes[lowest.saturating_sub(1)..] {
+            entry.run_view_hooks(ctx, &mut self.state, view);
+        }
+        Ok(())
+    }
+    /// Moves the running scene transition `secs` seconds forward
+    /// and starts the next change to the scene stack once it's done
+    fn advance_scenes(&mut self, ctx: &mut Context, secs: f32) -> GgezResult {
+        if let Some(running) = &mut self.transition {
+            running.elapsed += secs;
+            let progress = running.progress();
+            // Fades change the scenes when the screen is covered
+            let pending = if progress >= 0.5 { running.pending.take() } else { None };
+            if let Some(change) = pending {
+                self.change_scenes_now(ctx, change)?;
+            }
+            if progress >= 1. {
+                // A scene that slid out is gone now
+                if let Some(left) = self.transition.take().and_then(|running| running.leaving) {
+                    left.leave(ctx, &mut self.state)?;
+                }
+            }
+        }
+        while self.transition.is_none() {
+            let (change, transition) = match self.state.scene_changes.pop_front() {
+                Some(next) => next,
+                None => break,
+            };
+            match transition {
+                Transition::Cut => {
+                    self.change_scenes_now(ctx, change)?;
+                }
+                Transition::Fade { .. } => {
+                    self.transition = Some(Running {
+                        transition,
+                        elapsed: 0.,
+                        pending: Some(change),
+                        entering: false,
+                        leaving: None,
+                    });
+                }
+                Transition::Slide { .. } => {
+                    let entering = match change {
+                        SceneChange::Pop => false,
+                        SceneChange::Push(_) | SceneChange::Replace(_) => true,
+                    };
+                    let leaving = self.change_scenes(ctx, change)?;
+                    self.transition = Some(Running {
+                        transition,
+                        elapsed: 0.,
+                        pending: None,
+                        entering,
+                        leaving,
+                    });
+                }
             }
-            self.game.tick(ctx, &mut self.state, &mut self.object_set, DELTA)?;
         }
         Ok(())
     }
-    fn draw(&mut self, ctx: &mut Context) -> GgezResult {
-        graphics::clear(ctx, self.state.background);
+    /// Makes a change to the scene stack and lets the scene taken off it leave right away
+    fn change_scenes_now(&mut self, ctx: &mut Context, change: SceneChange) -> GgezResult {
+        match self.change_scenes(ctx, change)? {
+            Some(left) => left.leave(ctx, &mut self.state),
+            None => Ok(()),
+        }
+    }
+    /// Makes a change to the scene stack, returning the scene taken off it
+    fn change_scenes(&mut self, ctx: &mut Context, change: SceneChange) -> GgezResult<Option<SceneEntry>> {
+        Ok(match change {
+            SceneChange::Push(scene) => {
+                self.enter_scene(ctx, scene)?;
+                None
+            }
+            SceneChange::Pop => self.leave_scene(),
+            SceneChange::Replace(scene) => {
+                let left = self.leave_scene();
+                self.enter_scene(ctx, scene)?;
+                left
+            }
+        })
+    }
+    fn enter_scene(&mut self, ctx: &mut Context, scene: Box<dyn Scene>) -> GgezResult {
+        if let Some(context) = scene.context() {
+            self.state.push_context(context);
+        }
+        let entry = SceneEntry::enter(ctx, &mut self.state, scene)?;
+        self.scenes.push(entry);
+        Ok(())
+    }
+    /// Takes the top scene off the stack along with its input context
+    fn leave_scene(&mut self) -> Option<SceneEntry> {
+        let entry = self.scenes.pop();
+        match &entry {
+            // Take off the scene's context even if others were pushed on top of it since
+            Some(entry) => if let Some(context) = entry.scene.context() {
+                self.state.remove_context(context);
+            }
+            None => warn!("Tried to pop a scene with no scenes on the stack"),
+        }
+        entry
+    }
+    /// Draws the objects of the game or a scene and what it draws itself,
+    /// moved by `offset` on the screen
+    fn draw_layer(&self, ctx: &mut Context, entry: Option<&SceneEntry>, offset: Vector2, alpha: f32) -> GgezResult {
+        let object_set = entry.map(|entry| &entry.object_set).unwrap_or(&self.object_set);
+        let shift = Matrix4::new_translation(&Vector3::new(offset.x, offset.y, 0.));
 
-        graphics::push_transform(ctx, Some(Matrix4::new_translation(&self.state.offset.fixed_resize(0.))));
+        graphics::push_transform(ctx, Some(shift * self.state.camera.transform(self.state.width, self.state.height)));
         graphics::apply_transformations(ctx)?;
 
-        for obj in self.object_set.iter() {
-            obj.draw(ctx, &self.state.textures)?;
+        for obj in object_set.iter_draw_order() {
+            obj.draw(ctx, &self.state, alpha)?;
+        }
+        match entry {
+            Some(entry) => entry.scene.draw(ctx, &self.state, object_set, alpha)?,
+            None => self.game.draw(ctx, &self.state, object_set, alpha)?,
         }
-        self.game.draw(ctx, &self.state, &self.object_set)?;
 
-        // Pop the offset tranformation to draw the UI on the screen
+        // Swap the camera tranformation for just the offset to draw the UI on the screen
         graphics::pop_transform(ctx);
+        graphics::push_transform(ctx, Some(shift));
         graphics::apply_transformations(ctx)?;
 
-        // Flip the buffers to see what we just drew
-        graphics::present(ctx)?;
+        for obj in object_set.iter_draw_order() {
+            obj.draw_hud(ctx, &self.state)?;
+        }
+        match entry {
+            Some(entry) => entry.scene.draw_hud(ctx, &self.state, object_set)?,
+            None => self.game.draw_hud(ctx, &self.state, object_set)?,
+        }
 
-        // Give the computer some time to do other things
-        timer::yield_now();
-        Ok(())
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)
     }
-    fn quit_event(&mut self, _ctx: &mut Context) -> bool { false }
-    fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) {
-        self.state.width = width;
-        self.state.height = height;
+    /// Tells the objects and the game about the collisions this tick
+    ///
+    /// An error from the game is stored like one from a handler,
+    /// so the rest still hear about their collisions and the commands are still applied.
+    fn handle_collisions(&mut self, ctx: &mut Context, contacts: Contacts) {
+        for collision in self.collisions.update(contacts) {
+            if let Some(obj) = self.object_set.get_dyn_mut(collision.a) {
+                obj.on_collision(ctx, &mut self.state, &collision);
+            }
+            if let Some(obj) = self.object_set.get_dyn_mut(collision.b) {
+                obj.on_collision(ctx, &mut self.state, &collision.flipped());
+            }
+            if let Err(e) = self.game.collision(ctx, &mut self.state, &mut self.object_set, &collision) {
+                self.state.error = Some(e);
+            }
+        }
+        self.object_set.apply(&mut self.state.commands);
     }
-    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods) {
-        if let Some(&name) = self.state.key_to_name.get(&keycode) {
-            if let Some(handler) = self.handlers.key_up_handlers.get_mut(name) {
-                if let Err(e) = handler(ctx, &mut self.game, &mut self.state, &mut self.object_set) {
-                    self.state.error = Some(e);
-                }
+    /// Stores the error of a handler and applies the commands it queued
+    fn handled(&mut self, res: GgezResult) {
+        if let Err(e) = res {
+            self.state.error = Some(e);
+        }
+        self.object_set.apply(&mut self.state.commands);
+    }
+    fn key_event(&mut self, ctx: &mut Context, event: KeyEvent, context: &'a str, name: &str) {
+        match event {
+            KeyEvent::Down => self.state.action_down(context, name),
+            KeyEvent::Up => self.state.action_up(context, name),
+            KeyEvent::Press => (),
+        }
+        let handler = self.handlers.get_mut(context).and_then(|handlers| match event {
+            KeyEvent::Up => handlers.key_up_handlers.get_mut(name),
+            KeyEvent::Down => handlers.key_down_handlers.get_mut(name),
+            KeyEvent::Press => handlers.key_press_handlers.get_mut(name),
+        });
+        if let Some(handler) = handler {
+            let res = handler(ctx, &mut self.game, &mut self.state, &mut self.object_set);
+            self.handled(res);
+        }
+
+        // The scene the context belongs to gets the action as well
+        let entry = self.scenes.iter_mut().rev().find(|entry| entry.scene.context() == Some(context));
+        if let Some(entry) = entry {
+            let res = entry.run(&mut self.state, |entry, state| match event {
+                KeyEvent::Up => entry.scene.key_up(ctx, state, &mut entry.object_set, name),
+                KeyEvent::Down => entry.scene.key_down(ctx, state, &mut entry.object_set, name),
+                KeyEvent::Press => entry.scene.key_press(ctx, state, &mut entry.object_set, name),
+            });
+            if let Err(e) = res {
+                self.state.error = Some(e);
             }
         }
     }
-    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, repeat: bool) {
-        if let Some(&name) = self.state.key_to_name.get(&keycode) {
+    fn input_down(&mut self, ctx: &mut Context, input: Input, repeat: bool) {
+        // Repeats go where the first press went
+        let routed = if repeat {
+            self.state.pressed_inputs.get(&input).cloned()
+        } else {
+            self.state.route(input)
+        };
+        if let Some((context, name)) = routed {
             if !repeat {
-                if let Some(handler) = self.handlers.key_down_handlers.get_mut(name) {
-                    if let Err(e) = handler(ctx, &mut self.game, &mut self.state, &mut self.object_set) {
-                        self.state.error = Some(e);
+                self.state.pressed_inputs.insert(input, (context, name.clone()));
+                self.key_event(ctx, KeyEvent::Down, context, &name);
+            }
+            self.key_event(ctx, KeyEvent::Press, context, &name);
+        }
+        if !repeat {
+            let (chords, sequences) = self.state.pressed(input);
+            for (context, name) in chords {
+                self.key_event(ctx, KeyEvent::Down, context, &name);
+                self.key_event(ctx, KeyEvent::Press, context, &name);
+            }
+            for (context, name) in sequences {
+                self.key_event(ctx, KeyEvent::Down, context, &name);
+                self.key_event(ctx, KeyEvent::Press, context, &name);
+                self.key_event(ctx, KeyEvent::Up, context, &name);
+            }
+        }
+    }
+    fn input_up(&mut self, ctx: &mut Context, input: Input) {
+        // Release the action the input pressed, even if the contexts changed since
+        if let Some((context, name)) = self.state.pressed_inputs.remove(&input) {
+            self.key_event(ctx, KeyEvent::Up, context, &name);
+        }
+        for (context, name) in self.state.released(input) {
+            self.key_event(ctx, KeyEvent::Up, context, &name);
+        }
+    }
+    fn drag_event(&mut self, ctx: &mut Context, button: MouseButton, phase: DragPhase, held: DragState, pos: MousePos) {
+        let (context, name) = match self.state.pressed_inputs.get(&Input::Mouse(button)) {
+            Some(routed) => routed.clone(),
+            None => return,
+        };
+        let handler = self.handlers.get_mut(context).and_then(|handlers| handlers.drag_handlers.get_mut(&*name));
+        if let Some(handler) = handler {
+            let drag = Drag {
+                phase,
+                button,
+                start: held.start,
+                pos,
+                delta: pos.screen - held.last,
+            };
+            let res = handler(ctx, &mut self.game, &mut self.state, &mut self.object_set, &drag);
+            self.handled(res);
+        }
+    }
+    /// Gets the number of a real gamepad, giving it the next one if it has none
+    fn pad_for(&mut self, id: GamepadId) -> Pad {
+        let next = Pad(self.pad_ids.len());
+        *self.pad_ids.entry(id).or_insert(next)
+    }
+    /// Sends out disconnect events for real gamepads that are gone
+    fn check_pads(&mut self, ctx: &mut Context) {
+        let mut gone: Vec<_> = self.pad_ids.iter()
+            .filter(|&(_, pad)| self.state.pads.contains(pad))
+            .filter(|&(&id, _)| !gamepad::gamepad(ctx, id).is_connected())
+            .map(|(_, &pad)| pad)
+            .collect();
+        gone.sort();
+        for pad in gone {
+            self.live_event(ctx, InputEvent::Pad(PadEvent::Disconnected(pad)));
+        }
+    }
+    fn pad_event(&mut self, ctx: &mut Context, event: PadEvent) {
+        let pad = match event {
+            PadEvent::Connected(pad)
+            | PadEvent::Disconnected(pad)
+            | PadEvent::ButtonDown(pad, _)
+            | PadEvent::ButtonUp(pad, _)
+            | PadEvent::Axis(pad, _, _) => pad,
+        };
+        let connected = self.state.pads.contains(&pad);
+        // Gamepads are only noticed once they're used
+        if !connected {
+            match event {
+                PadEvent::Disconnected(_) => return,
+                PadEvent::Connected(_) => (),
+                _ => self.pad_event(ctx, PadEvent::Connected(pad)),
+            }
+        }
+
+        match event {
+            PadEvent::Connected(_) => {
+                if !connected {
+                    self.state.pads.push(pad);
+                    let res = self.game.gamepad_connected(ctx, &mut self.state, &mut self.object_set, pad);
+                    self.handled(res);
+                }
+            }
+            PadEvent::Disconnected(_) => {
+                // Let go of everything still held on the gamepad
+                let mut held: Vec<_> = self.state.pad_buttons.iter()
+                    .filter(|&&(p, _)| p == pad)
+                    .map(|&(_, button)| button)
+                    .collect();
+                held.sort_by_key(|&button| button as u16);
+                for button in held {
+                    self.pad_event(ctx, PadEvent::ButtonUp(pad, button));
+                }
+                let mut axes: Vec<_> = self.state.pad_axes.keys()
+                    .filter(|&&(p, _)| p == pad)
+                    .map(|&(_, axis)| axis)
+                    .collect();
+                axes.sort_by_key(|&axis| axis as u16);
+                for axis in axes {
+                    self.pad_event(ctx, PadEvent::Axis(pad, axis, 0.));
+                }
+
+                self.state.pads.retain(|&p| p != pad);
+                let res = self.game.gamepad_disconnected(ctx, &mut self.state, &mut self.object_set, pad);
+                self.handled(res);
+            }
+            PadEvent::ButtonDown(_, button) => {
+                let any_down = self.state.pad_button_down(None, button);
+                if self.state.pad_buttons.insert((pad, button)) {
+                    self.input_down(ctx, Input::PadButton(Some(pad), button), false);
+                    if !any_down {
+                        self.input_down(ctx, Input::PadButton(None, button), false);
+                    }
+                }
+            }
+            PadEvent::ButtonUp(_, button) => {
+                if self.state.pad_buttons.remove(&(pad, button)) {
+                    self.input_up(ctx, Input::PadButton(Some(pad), button));
+                    if !self.state.pad_button_down(None, button) {
+                        self.input_up(ctx, Input::PadButton(None, button));
                     }
                 }
             }
-            if let Some(handler) = self.handlers.key_press_handlers.get_mut(name) {
-                if let Err(e) = handler(ctx, &mut self.game, &mut self.state, &mut self.object_set) {
-                    self.state.error = Some(e);
+            PadEvent::Axis(_, axis, value) => {
+                let dirs = [AxisDirection::Negative, AxisDirection::Positive];
+                let before: Vec<_> = dirs.iter()
+                    .map(|&dir| (self.state.pad_axis_pushed(Some(pad), axis, dir), self.state.pad_axis_pushed(None, axis, dir)))
+                    .collect();
+                self.state.pad_axes.insert((pad, axis), value);
+
+                for (&dir, &(was_pushed, any_was_pushed)) in dirs.iter().zip(&before) {
+                    let pushed = self.state.pad_axis_pushed(Some(pad), axis, dir);
+                    let any_pushed = self.state.pad_axis_pushed(None, axis, dir);
+                    let inputs = [
+                        (was_pushed, pushed, Input::PadAxis(Some(pad), axis, dir)),
+                        (any_was_pushed, any_pushed, Input::PadAxis(None, axis, dir)),
+                    ];
+                    for &(was, now, input) in &inputs {
+                        match (was, now) {
+                            (false, true) => self.input_down(ctx, input, false),
+                            (true, false) => self.input_up(ctx, input),
+                            _ => (),
+                        }
+                    }
                 }
             }
         }
     }
 }
 
+impl<'a, G: Game> GameState<'a, G> {
+    /// Handles an event from the player, unless a recording is being played back
+    fn live_event(&mut self, ctx: &mut Context, event: InputEvent) {
+        if !self.state.is_playing() {
+            self.input_event(ctx, event);
+        }
+    }
+    /// Records an input event and sends it on to the handlers
+    fn input_event(&mut self, ctx: &mut Context, event: InputEvent) {
+        self.state.record(event);
+        match event {
+            InputEvent::KeyDown { key, mods, repeat } => self.key_down(ctx, key, mods, repeat),
+            InputEvent::KeyUp { key, mods } => self.key_up(ctx, key, mods),
+            InputEvent::MouseDown { button, pos } => self.mouse_down(ctx, button, pos),
+            InputEvent::MouseUp { button, pos } => self.mouse_up(ctx, button, pos),
+            InputEvent::MouseMotion { pos } => self.mouse_motion(ctx, pos),
+            InputEvent::Wheel { x, y } => self.mouse_wheel(ctx, x, y),
+            InputEvent::Pad(event) => self.pad_event(ctx, event),
+        }
+    }
+    fn key_up(&mut self, ctx: &mut Context, key: KeyCode, _mods: KeyMods) {
+        // Release what the key was pressed as, even if the modifiers were let go first
+        let input = self.state.keys_down.remove(&key).unwrap_or(Input::Key(key));
+        self.input_up(ctx, input);
+    }
+    fn key_down(&mut self, ctx: &mut Context, key: KeyCode, mods: KeyMods, repeat: bool) {
+        let input = match self.state.keys_down.get(&key) {
+            Some(&input) if repeat => input,
+            _ => self.state.resolve_key(key, mods),
+        };
+        self.state.keys_down.insert(key, input);
+        self.input_down(ctx, input, repeat);
+    }
+    fn mouse_down(&mut self, ctx: &mut Context, button: MouseButton, screen: Point2) {
+        self.state.mouse = screen;
+        let pos = self.state.mouse_pos_at(screen);
+        self.drags.insert(button, DragState {
+            start: pos,
+            last: pos.screen,
+            dragging: false,
+        });
+
+        self.input_down(ctx, Input::Mouse(button), false);
+        if let Some((context, name)) = self.state.pressed_inputs.get(&Input::Mouse(button)).cloned() {
+            let handler = self.handlers.get_mut(context).and_then(|handlers| handlers.mouse_down_handlers.get_mut(&*name));
+            if let Some(handler) = handler {
+                let res = handler(ctx, &mut self.game, &mut self.state, &mut self.object_set, pos);
+                self.handled(res);
+            }
+        }
+    }
+    fn mouse_up(&mut self, ctx: &mut Context, button: MouseButton, screen: Point2) {
+        self.state.mouse = screen;
+        let pos = self.state.mouse_pos_at(screen);
+        let routed = self.state.pressed_inputs.get(&Input::Mouse(button)).cloned();
+        if let Some(drag) = self.drags.remove(&button) {
+            if drag.dragging {
+                self.drag_event(ctx, button, DragPhase::End, drag, pos);
+            }
+        }
+
+        self.input_up(ctx, Input::Mouse(button));
+        if let Some((context, name)) = routed {
+            let handler = self.handlers.get_mut(context).and_then(|handlers| handlers.mouse_up_handlers.get_mut(&*name));
+            if let Some(handler) = handler {
+                let res = handler(ctx, &mut self.game, &mut self.state, &mut self.object_set, pos);
+                self.handled(res);
+            }
+        }
+    }
+    fn mouse_motion(&mut self, ctx: &mut Context, screen: Point2) {
+        self.state.mouse = screen;
+        let pos = self.state.mouse_pos_at(screen);
+
+        let mut drags: Vec<_> = self.drags.iter().map(|(&button, &drag)| (button, drag)).collect();
+        // Keep the order of the handlers the same between runs
+        drags.sort_by_key(|&(button, _)| mouse_button_order(button));
+        for (button, mut drag) in drags {
+            let phase = if drag.dragging {
+                DragPhase::Move
+            } else if (pos.screen - drag.start.screen).norm() >= DRAG_THRESHOLD {
+                DragPhase::Start
+            } else {
+                continue;
+            };
+            self.drag_event(ctx, button, phase, drag, pos);
+            drag.dragging = true;
+            drag.last = pos.screen;
+            self.drags.insert(button, drag);
+        }
+
+        let contexts: Vec<_> = self.state.reachable_contexts().into_iter().map(|(name, _)| name).collect();
+        for context in contexts {
+            let handler = self.handlers.get_mut(context).and_then(|handlers| handlers.mouse_motion_handler.as_mut());
+            if let Some(handler) = handler {
+                let res = handler(ctx, &mut self.game, &mut self.state, &mut self.object_set, pos);
+                self.handled(res);
+            }
+        }
+    }
+    fn mouse_wheel(&mut self, ctx: &mut Context, x: f32, y: f32) {
+        let dirs = [
+            (y > 0., WheelDirection::Up),
+            (y < 0., WheelDirection::Down),
+            (x < 0., WheelDirection::Left),
+            (x > 0., WheelDirection::Right),
+        ];
+        for &(scrolled, dir) in &dirs {
+            if scrolled {
+                self.input_down(ctx, Input::Wheel(dir), false);
+                self.input_up(ctx, Input::Wheel(dir));
+            }
+        }
+    }
+}
+
+impl<G: Game> EventHandler for GameState<'_, G> {
+    fn update(&mut self, ctx: &mut Context) -> GgezResult {
+        let frame_time = timer::delta(ctx).as_secs_f32();
+        self.update_by(ctx, frame_time)
+    }
+    fn draw(&mut self, ctx: &mut Context) -> GgezResult {
+        graphics::clear(ctx, self.state.background);
+        // How far we are from the last tick to the next
+        let alpha = (self.state.accumulator / self.state.delta()).min(1.);
+        let (width, height) = self.state.dims();
+
+        let (progress, transition) = match &self.transition {
+            Some(running) => (running.progress(), running.transition),
+            None => (1., Transition::Cut),
+        };
+        let entering = self.transition.as_ref().map(|running| running.entering).unwrap_or(false);
+
+        let top = self.scenes.len();
+        for layer in scene::lowest_layer(&self.scenes, |scene| scene.draw_below())..=top {
+            let offset = match transition {
+                Transition::Slide { from, .. } if entering && layer == top => from.offset(width, height) * (1. - progress),
+                _ => Vector2::zeros(),
+            };
+            let entry = if layer == 0 { None } else { Some(&self.scenes[layer - 1]) };
+            self.draw_layer(ctx, entry, offset, alpha)?;
+        }
+
+        if let Some(leaving) = self.transition.as_ref().and_then(|running| running.leaving.as_ref()) {
+            if let Transition::Slide { from, .. } = transition {
+                // A replaced scene is pushed out the other side by the new one
+                let dir = if entering { -1. } else { 1. };
+                self.draw_layer(ctx, Some(leaving), from.offset(width, height) * dir * progress, alpha)?;
+            }
+        }
+        if let Transition::Fade { color, .. } = transition {
+            let cover = 1. - (2. * progress - 1.).abs();
+            let color = Color { a: color.a * cover, .. color };
+            let rect = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), Rect::new(0., 0., width, height), color)?;
+            graphics::draw(ctx, &rect, graphics::DrawParam::default())?;
+        }
+
+        // Flip the buffers to see what we just drew
+        graphics::present(ctx)?;
+
+        // Give the computer some time to do other things
+        timer::yield_now();
+        Ok(())
+    }
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool { false }
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.state.width = width;
+        self.state.height = height;
+        // Keep one unit on the screen equal to one pixel so the mouse lines up
+        if let Err(e) = graphics::set_screen_coordinates(ctx, Rect::new(0., 0., width, height)) {
+            self.state.error = Some(e);
+        }
+    }
+    fn key_up_event(&mut self, ctx: &mut Context, key: KeyCode, mods: KeyMods) {
+        self.live_event(ctx, InputEvent::KeyUp { key, mods });
+    }
+    fn key_down_event(&mut self, ctx: &mut Context, key: KeyCode, mods: KeyMods, repeat: bool) {
+        self.live_event(ctx, InputEvent::KeyDown { key, mods, repeat });
+    }
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        self.live_event(ctx, InputEvent::MouseDown { button, pos: Point2::new(x, y) });
+    }
+    fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        self.live_event(ctx, InputEvent::MouseUp { button, pos: Point2::new(x, y) });
+    }
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        self.live_event(ctx, InputEvent::MouseMotion { pos: Point2::new(x, y) });
+    }
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) {
+        self.live_event(ctx, InputEvent::Wheel { x, y });
+    }
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        let pad = self.pad_for(id);
+        self.live_event(ctx, InputEvent::Pad(PadEvent::ButtonDown(pad, button)));
+    }
+    fn gamepad_button_up_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        let pad = self.pad_for(id);
+        self.live_event(ctx, InputEvent::Pad(PadEvent::ButtonUp(pad, button)));
+    }
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        let pad = self.pad_for(id);
+        self.live_event(ctx, InputEvent::Pad(PadEvent::Axis(pad, axis, value)));
+    }
+}
+
+fn mouse_button_order(button: MouseButton) -> u16 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Other(n) => 3 + u16::from(n),
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     GgezError(GgezError),
@@ -367,10 +1701,116 @@ pub trait Game: Sized {
     fn logic(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet) -> GgezResult { Ok(()) }
     /// This is run every tick
     fn tick(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _delta: f32) -> GgezResult { Ok(()) }
+    /// This is run every update with the real time since the last one,
+    /// even while the game is paused or slowed down
+    fn update_unscaled(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _delta: f32) -> GgezResult { Ok(()) }
+    /// This is run for every collision each tick, after the objects have been told about it
+    fn collision(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _: &Collision) -> GgezResult { Ok(()) }
+    /// This is run when a gamepad is first used or comes back after being disconnected
+    fn gamepad_connected(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _pad: Pad) -> GgezResult { Ok(()) }
+    /// This is run when a gamepad is disconnected, after everything held on it has been let go
+    fn gamepad_disconnected(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _pad: Pad) -> GgezResult { Ok(()) }
     /// This function should draw other things on the screen
-    /// that follow the offset
-    fn draw(&self, _ctx: &mut Context, _state: &State, _: &ObjectSet) -> GgezResult { Ok(()) }
+    /// that follow the camera
+    ///
+    /// `alpha` is how far the time is between the last tick and the next, from 0 to 1.
+    fn draw(&self, _ctx: &mut Context, _state: &State, _: &ObjectSet, _alpha: f32) -> GgezResult { Ok(()) }
     /// This should draw things on top of the what's drawn in `draw`
-    /// and that do not follow the offset
+    /// and that do not follow the camera
     fn draw_hud(&self, _ctx: &mut Context, _state: &State, _: &ObjectSet) -> GgezResult { Ok(()) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPAWN: &str = "spawn";
+
+    fn state<'a>() -> State<'a> {
+        State::with_size(800., 600., &ObjectSet::new(), 60, Textures::without_assets())
+    }
+
+    #[test]
+    fn presses_are_seen_by_one_tick() {
+        let mut state = state();
+        state.action_down(DEFAULT_CONTEXT, SPAWN);
+        assert!(!state.just_pressed(SPAWN));
+
+        state.begin_tick();
+        assert!(state.just_pressed(SPAWN));
+        state.begin_tick();
+        assert!(!state.just_pressed(SPAWN));
+
+        state.action_up(DEFAULT_CONTEXT, SPAWN);
+        state.begin_tick();
+        assert!(state.just_released(SPAWN));
+        assert_eq!(state.released_after(SPAWN), Some(2. * state.delta()));
+        state.begin_tick();
+        assert!(!state.just_released(SPAWN));
+    }
+
+    #[test]
+    fn actions_stay_down_until_every_input_is_released() {
+        let mut state = state();
+        state.action_down(DEFAULT_CONTEXT, SPAWN);
+        state.action_down(DEFAULT_CONTEXT, SPAWN);
+        state.begin_tick();
+        state.action_up(DEFAULT_CONTEXT, SPAWN);
+        state.begin_tick();
+        assert!(!state.just_released(SPAWN));
+        state.action_up(DEFAULT_CONTEXT, SPAWN);
+        state.begin_tick();
+        assert!(state.just_released(SPAWN));
+    }
+
+    #[test]
+    fn actions_of_the_same_name_in_different_contexts_are_kept_apart() {
+        let mut state = state();
+        state.context_mut(DEFAULT_CONTEXT).bind_key(KeyCode::Return, SPAWN);
+        let menu = state.context_mut("menu");
+        menu.pass_through = true;
+        menu.bind_key(KeyCode::Space, SPAWN);
+        state.push_context("menu");
+
+        let (context, name) = state.route(Input::Key(KeyCode::Return)).unwrap();
+        assert_eq!((context, &*name), (DEFAULT_CONTEXT, SPAWN));
+        state.action_down(context, &name);
+        let (context, name) = state.route(Input::Key(KeyCode::Space)).unwrap();
+        assert_eq!((context, &*name), ("menu", SPAWN));
+        state.action_down(context, &name);
+        state.begin_tick();
+        assert!(state.just_pressed_in(DEFAULT_CONTEXT, SPAWN));
+        assert!(state.just_pressed_in("menu", SPAWN));
+
+        state.action_up("menu", SPAWN);
+        state.begin_tick();
+        assert!(state.just_released_in("menu", SPAWN));
+        assert!(!state.just_released_in(DEFAULT_CONTEXT, SPAWN));
+        assert!(state.just_released(SPAWN));
+        assert_eq!(state.held_for_in(DEFAULT_CONTEXT, SPAWN), state.delta());
+    }
+
+    #[test]
+    fn pausing_stepping_and_time_scale_change_the_ticks_run() {
+        let mut state = state();
+        let delta = state.delta();
+
+        state.pause();
+        assert_eq!(state.take_ticks(1.), 0);
+        state.step();
+        assert_eq!(state.take_ticks(0.), 1);
+        assert_eq!(state.take_ticks(0.), 0);
+
+        state.resume();
+        state.time_scale = 0.5;
+        assert_eq!(state.take_ticks(1.5 * delta), 0);
+        assert_eq!(state.take_ticks(3.5 * delta), 2);
+    }
+
+    #[test]
+    fn falling_behind_gives_up_on_catching_up() {
+        let mut state = state();
+        assert_eq!(state.take_ticks(1.), MAX_TICKS_PER_UPDATE);
+        assert_eq!(state.take_ticks(0.), 0);
+    }
+}
@@ -1,169 +1,672 @@
 use crate::State;
-use std::borrow::Borrow;
-use std::fmt::{self, Debug};
+use std::any::Any;
 use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::fmt::{self, Debug};
 use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 
-use byteorder::{ByteOrder, BigEndian};
+use ggez::graphics::Rect;
 
-#[derive(Clone)]
-struct HashedPointer<T: ?Sized>(Box<T>);
+use crate::spatial::SpatialHash;
+use crate::util::Point2;
 
-impl HashedPointer<dyn Object> {
-    #[inline(always)]
-    fn ptr<T>(&self) -> *const T {
-        Borrow::<ObjectId<T>>::borrow(self).0
-    }
-    #[inline(always)]
-    fn n(&self) -> usize {
-        self.ptr() as *const () as usize
-    }
+/// The layer an object is drawn in
+///
+/// Lower layers are drawn first. Objects in the same layer are drawn
+/// in the order they were added to (or last moved into) the layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Layer(pub i32);
+
+impl Layer {
+    pub const BACKGROUND: Layer = Layer(-100);
+    pub const WORLD: Layer = Layer(0);
+    pub const FOREGROUND: Layer = Layer(100);
 }
 
-impl Debug for HashedPointer<dyn Object> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let o = *Borrow::<ObjectId<()>>::borrow(self);
-        o.fmt(fmt)
-        // write!(fmt, "hp@{:p}", &*self.0)
-    }
+/// Hands out slots for new objects
+///
+/// Shared between an `ObjectSet` and its `Commands`,
+/// so that ids can be given out while the set is borrowed.
+#[derive(Debug, Default)]
+struct Allocator {
+    /// Indices of empty slots and the generation they will have next
+    free: Vec<(u32, u32)>,
+    next_index: u32,
 }
 
-impl Hash for HashedPointer<dyn Object> {
-    fn hash<H: Hasher>(&self, h: &mut H) {
-        let mut bytes = [0; ::std::mem::size_of::<usize>()];
-        BigEndian::write_u64(&mut bytes, self.n() as u64);
-        h.write(&bytes)
-    }
+/// Locks an allocator
+///
+/// Nothing can panic while it's locked, but a poisoned lock is fine to use anyway.
+#[inline]
+fn lock(alloc: &Mutex<Allocator>) -> MutexGuard<'_, Allocator> {
+    alloc.lock().unwrap_or_else(|e| e.into_inner())
 }
 
-impl PartialEq<Self> for HashedPointer<dyn Object> {
-    #[inline]
-    fn eq(&self, other: &Self) -> bool {
-        self.n() == other.n()
+impl Allocator {
+    fn allocate(&mut self) -> (u32, u32) {
+        let next_index = &mut self.next_index;
+        self.free.pop().unwrap_or_else(|| {
+            let index = *next_index;
+            *next_index += 1;
+            (index, 0)
+        })
     }
 }
-impl<T: ?Sized> PartialEq<ObjectId<T>> for HashedPointer<dyn Object> {
-    #[inline]
-    fn eq(&self, other: &ObjectId<T>) -> bool {
-        self.n() == other.n()
-    }
+
+struct Slot {
+    generation: u32,
+    layer: Layer,
+    /// When the object was put in its layer, to keep the draw order stable
+    seq: u64,
+    tags: Vec<Tag>,
+    /// Whether the object was in view of the camera at the end of the last tick
+    in_view: bool,
+    obj: Option<Box<dyn Object>>,
 }
-impl<T> Borrow<ObjectId<T>> for HashedPointer<dyn Object> {
-    fn borrow(&self) -> &ObjectId<T> {
-        unsafe {
-            &*(&*self.0 as *const dyn Object as *const () as *const T as *const ObjectId<T>)
-        }
+
+impl Slot {
+    /// Whether the object in this slot is in none of the given groups
+    #[inline]
+    fn outside(&self, groups: &HashSet<Tag>) -> bool {
+        groups.is_empty() || !self.tags.iter().any(|t| groups.contains(t))
     }
 }
-impl Eq for HashedPointer<dyn Object> {}
-// TODO maybe use an unsafe cell instead, to avoid undefined behaviour from optimisations
-// in iter_mut
-#[derive(Debug)]
+
+/// The set of all objects in the game
+///
+/// Objects are stored in slots that are reused after removal.
+/// Every reuse bumps the generation of the slot, so an old `ObjectId`
+/// never refers to an object added after it was removed.
+#[derive(Default)]
 pub struct ObjectSet {
-    set: HashSet<HashedPointer<dyn Object>>,
+    slots: Vec<Slot>,
+    alloc: Arc<Mutex<Allocator>>,
+    len: usize,
+    /// Indices of the occupied slots sorted by layer and then `seq`
+    draw_order: Vec<(Layer, u64, u32)>,
+    next_seq: u64,
+    /// Groups that aren't updated
+    inactive: HashSet<Tag>,
+    /// Groups that aren't drawn
+    hidden: HashSet<Tag>,
+    /// Where the objects were when the index was last rebuilt
+    spatial: SpatialHash,
+    /// Objects added that haven't had `on_added` run yet
+    added: Vec<ObjectId<dyn Object>>,
+    /// Objects dropped by the set that haven't had `on_removed` run yet
+    dropped: Vec<Box<dyn Object>>,
 }
 
-impl Default for ObjectSet {
-    fn default() -> Self {
-        Self::new()
+impl Debug for ObjectSet {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ObjectSet")
+            .field("len", &self.len)
+            .field("slots", &self.slots.len())
+            .finish()
     }
 }
 
 impl ObjectSet {
     pub fn new() -> Self {
         ObjectSet {
-            set: HashSet::new(),
+            slots: Vec::new(),
+            alloc: Arc::default(),
+            len: 0,
+            draw_order: Vec::new(),
+            next_seq: 0,
+            inactive: HashSet::new(),
+            hidden: HashSet::new(),
+            spatial: SpatialHash::default(),
+            added: Vec::new(),
+            dropped: Vec::new(),
         }
     }
-    pub fn add<O: 'static + Object>(&mut self, obj: O) -> ObjectId<O> {
-        let hp: HashedPointer<dyn Object> = HashedPointer(Box::new(obj));
-        let obj_id = *Borrow::<ObjectId<O>>::borrow(&hp);
-        self.set.insert(hp);
-        obj_id
+    fn insert_draw_order(&mut self, layer: Layer, index: u32) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        // `seq` is the largest yet, so this goes at the end of its layer
+        let pos = match self.draw_order.binary_search(&(layer, seq, index)) {
+            Ok(pos) | Err(pos) => pos,
+        };
+        self.draw_order.insert(pos, (layer, seq, index));
+        seq
     }
-    // TODO: maybe return T
-    pub fn remove<T>(&mut self, id: ObjectId<T>) -> Option<Box<dyn Object>> {
-        self.set.take(&id).map(|HashedPointer(b)| b)
+    fn remove_draw_order(&mut self, layer: Layer, seq: u64, index: u32) {
+        if let Ok(pos) = self.draw_order.binary_search(&(layer, seq, index)) {
+            self.draw_order.remove(pos);
+        }
     }
-    pub fn get<O: 'static + Object>(&self, id: ObjectId<O>) -> Option<&O> {
-        self.set.get(&id).map(|hp| unsafe {
-            &*(&*hp.0 as *const (dyn 'static + Object) as *const O)
-        })
+    #[inline]
+    fn slot(&self, index: u32, generation: u32) -> Option<&dyn Object> {
+        match self.slots.get(index as usize) {
+            Some(Slot { generation: g, obj: Some(obj), .. }) if *g == generation => Some(&**obj),
+            _ => None,
+        }
     }
-    pub fn get_mut<'a, O: 'static + Object>(&'a mut self, id: ObjectId<O>) -> Option<&'a mut O> {
-        self.set.get(&id).map(|hp| unsafe {
-            &mut *(&*hp.0 as *const dyn Object as *mut dyn Object as *mut O)
-        })
+    #[inline]
+    fn slot_mut(&mut self, index: u32, generation: u32) -> Option<&mut (dyn Object + 'static)> {
+        match self.slots.get_mut(index as usize) {
+            Some(Slot { generation: g, obj: Some(obj), .. }) if *g == generation => Some(&mut **obj),
+            _ => None,
+        }
+    }
+    /// Adds an object in the `WORLD` layer
+    #[inline]
+    pub fn add<O: Object>(&mut self, obj: O) -> ObjectId<O> {
+        self.add_to_layer(obj, Layer::WORLD)
+    }
+    /// Adds an object on top of the given layer
+    pub fn add_to_layer<O: Object>(&mut self, obj: O, layer: Layer) -> ObjectId<O> {
+        let (index, generation) = lock(&self.alloc).allocate();
+        self.insert(index, generation, layer, Box::new(obj));
+        ObjectId::new(index, generation)
+    }
+    /// Puts an object in a slot given out by the allocator
+    fn insert(&mut self, index: u32, generation: u32, layer: Layer, obj: Box<dyn Object>) {
+        // Slots reserved by `Commands` may not have been filled yet
+        while self.slots.len() <= index as usize {
+            self.slots.push(Slot {
+                generation: 0,
+                layer: Layer::WORLD,
+                seq: 0,
+                tags: Vec::new(),
+                in_view: false,
+                obj: None,
+            });
+        }
+        let seq = self.insert_draw_order(layer, index);
+
+        let slot = &mut self.slots[index as usize];
+        debug_assert!(slot.obj.is_none());
+        slot.generation = generation;
+        slot.obj = Some(obj);
+        slot.layer = layer;
+        slot.seq = seq;
+        slot.in_view = false;
+        self.len += 1;
+        self.added.push(ObjectId::new(index, generation));
+    }
+    /// Removes an object and gives it back
+    ///
+    /// Does nothing if the object isn't of type `O`.
+    /// `Object::on_removed` isn't run, since the object isn't gone yet.
+    pub fn remove<O: Object>(&mut self, id: ObjectId<O>) -> Option<O> {
+        // Objects of other types stay in the set
+        self.get(id)?;
+        self.remove_boxed(id)
+            .and_then(|obj| obj.into_any().downcast().ok())
+            .map(|obj| *obj)
+    }
+    /// Removes an object no matter its type
+    pub fn remove_boxed<T: ?Sized>(&mut self, id: ObjectId<T>) -> Option<Box<dyn Object>> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        let obj = slot.obj.take()?;
+        slot.tags.clear();
+        slot.generation = slot.generation.wrapping_add(1);
+        let (layer, seq) = (slot.layer, slot.seq);
+        lock(&self.alloc).free.push((id.index, slot.generation));
+        self.remove_draw_order(layer, seq, id.index);
+        self.len -= 1;
+        Some(obj)
+    }
+    /// Gets the layer an object is drawn in
+    pub fn layer<T: ?Sized>(&self, id: ObjectId<T>) -> Option<Layer> {
+        if self.contains(id) {
+            Some(self.slots[id.index as usize].layer)
+        } else {
+            None
+        }
+    }
+    /// Moves an object to the top of the given layer
+    ///
+    /// Returns `false` if the object doesn't exist.
+    pub fn set_layer<T: ?Sized>(&mut self, id: ObjectId<T>, layer: Layer) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        let Slot { layer: old_layer, seq: old_seq, .. } = self.slots[id.index as usize];
+        self.remove_draw_order(old_layer, old_seq, id.index);
+        let seq = self.insert_draw_order(layer, id.index);
+
+        let slot = &mut self.slots[id.index as usize];
+        slot.layer = layer;
+        slot.seq = seq;
+        true
+    }
+    #[inline]
+    pub fn contains<T: ?Sized>(&self, id: ObjectId<T>) -> bool {
+        self.slot(id.index, id.generation).is_some()
+    }
+    /// Gets an object no matter its type
+    #[inline]
+    pub fn get_dyn<T: ?Sized>(&self, id: ObjectId<T>) -> Option<&dyn Object> {
+        self.slot(id.index, id.generation)
+    }
+    /// Gets an object mutably no matter its type
+    #[inline]
+    pub fn get_dyn_mut<T: ?Sized>(&mut self, id: ObjectId<T>) -> Option<&mut (dyn Object + 'static)> {
+        self.slot_mut(id.index, id.generation)
+    }
+    pub fn get<O: Object>(&self, id: ObjectId<O>) -> Option<&O> {
+        self.slot(id.index, id.generation)
+            .and_then(|obj| obj.as_any().downcast_ref())
+    }
+    pub fn get_mut<O: Object>(&mut self, id: ObjectId<O>) -> Option<&mut O> {
+        self.slot_mut(id.index, id.generation)
+            .and_then(|obj| obj.as_any_mut().downcast_mut())
     }
     pub fn iter(&self) -> impl Iterator<Item=&dyn Object> {
-        self.set.iter()
-            .map(|hp| {
-                &*hp.0
+        self.slots.iter()
+            .filter_map(|slot| slot.obj.as_ref())
+            .map(|obj| &**obj)
+    }
+    /// Iterates over all objects along with their ids
+    pub fn iter_with_ids(&self) -> impl Iterator<Item=(ObjectId<dyn Object>, &dyn Object)> {
+        self.slots.iter()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let obj = slot.obj.as_deref()?;
+                Some((ObjectId::new(index as u32, slot.generation), obj))
+            })
+    }
+    /// Rebuilds the spatial index from where the objects are now
+    ///
+    /// This is done every tick after the objects have updated,
+    /// so the queries below see the objects where they were at that point.
+    pub fn rebuild_spatial_index(&mut self) {
+        let cell_size = self.spatial.cell_size();
+        self.set_cell_size(cell_size);
+    }
+    /// Changes the size of the cells in the spatial index and rebuilds it
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cell_size` isn't a positive finite number.
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        let mut spatial = SpatialHash::new(cell_size);
+        for (id, obj) in self.iter_with_ids() {
+            let bounds = match (obj.bounds(), obj.pos()) {
+                (Some(bounds), _) => bounds,
+                (None, Some(p)) => Rect::new(p.x, p.y, 0., 0.),
+                (None, None) => continue,
+            };
+            spatial.insert(id, bounds);
+        }
+        self.spatial = spatial;
+    }
+    /// Finds the objects whose bounds overlap the rectangle
+    #[inline]
+    pub fn query_rect(&self, rect: Rect) -> Vec<ObjectId<dyn Object>> {
+        self.spatial.query_rect(rect)
+    }
+    /// Finds the objects whose bounds are within `radius` of `centre`
+    #[inline]
+    pub fn query_circle(&self, centre: Point2, radius: f32) -> Vec<ObjectId<dyn Object>> {
+        self.spatial.query_circle(centre, radius)
+    }
+    /// Finds the object whose bounds are closest to `p`
+    #[inline]
+    pub fn nearest(&self, p: Point2) -> Option<ObjectId<dyn Object>> {
+        self.spatial.nearest(p)
+    }
+    /// Finds the objects the line segment from `from` to `to` goes through,
+    /// closest first, along with how far along the segment they are hit
+    #[inline]
+    pub fn raycast(&self, from: Point2, to: Point2) -> Vec<(ObjectId<dyn Object>, f32)> {
+        self.spatial.raycast(from, to)
+    }
+    /// Iterates over all objects of type `O`
+    pub fn iter_of<O: Object>(&self) -> impl Iterator<Item=(ObjectId<O>, &O)> {
+        self.slots.iter()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let obj = slot.obj.as_deref()?.as_any().downcast_ref()?;
+                Some((ObjectId::new(index as u32, slot.generation), obj))
             })
     }
-    pub fn iter_mut(&mut self) -> impl Iterator<Item=&mut dyn Object> {
-        self.set.iter()
-            // SAFETY:
-            // should be fine since this function takes a mutable reference
-            // whose lifetime the mutables references generated for the iterator
-            .map(|hp| unsafe {
-                &mut *(&*hp.0 as *const dyn Object as *mut dyn Object)
+    /// Iterates mutably over all objects of type `O`
+    pub fn iter_of_mut<O: Object>(&mut self) -> impl Iterator<Item=(ObjectId<O>, &mut O)> {
+        self.slots.iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let generation = slot.generation;
+                let obj = slot.obj.as_deref_mut()?.as_any_mut().downcast_mut()?;
+                Some((ObjectId::new(index as u32, generation), obj))
             })
     }
+    /// Counts the objects of type `O`
+    pub fn count_of<O: Object>(&self) -> usize {
+        self.iter_of::<O>().count()
+    }
+    /// Iterates over the objects that aren't hidden in the order they should be drawn
+    pub fn iter_draw_order(&self) -> impl Iterator<Item=&dyn Object> {
+        let slots = &self.slots;
+        let hidden = &self.hidden;
+        self.draw_order.iter()
+            .map(move |&(_, _, index)| &slots[index as usize])
+            .filter(move |slot| slot.outside(hidden))
+            .filter_map(|slot| slot.obj.as_deref())
+    }
+    /// Finds the object drawn on top at the given point in the world
+    ///
+    /// Objects with a collider are found by its shape and the rest by their `bounds`,
+    /// so objects with neither can't be found.
+    pub fn topmost_at(&self, p: Point2) -> Option<ObjectId<dyn Object>> {
+        self.draw_order.iter()
+            .rev()
+            .map(|&(_, _, index)| (index, &self.slots[index as usize]))
+            .filter(|(_, slot)| slot.outside(&self.hidden))
+            .find_map(|(index, slot)| {
+                let obj = slot.obj.as_deref()?;
+                let hit = match obj.collider() {
+                    Some(collider) => collider.contains(p),
+                    None => obj.bounds()?.contains(p),
+                };
+                if hit {
+                    Some(ObjectId::new(index, slot.generation))
+                } else {
+                    None
+                }
+            })
+    }
+    /// Iterates mutably over the objects that aren't in an inactive group
+    pub fn iter_active_mut(&mut self) -> impl Iterator<Item=&mut (dyn Object + 'static)> {
+        let inactive = &self.inactive;
+        self.slots.iter_mut()
+            .filter(move |slot| slot.outside(inactive))
+            .filter_map(|slot| slot.obj.as_deref_mut())
+    }
+    /// Runs `f` on every object that isn't in an inactive group along with the set
+    ///
+    /// Each object is taken out of its slot while `f` runs on it,
+    /// so the set it sees has every object but itself.
+    pub(crate) fn update_each<F: FnMut(&mut (dyn Object + 'static), &ObjectSet)>(&mut self, mut f: F) {
+        for index in 0..self.slots.len() {
+            let slot = &mut self.slots[index];
+            if !slot.outside(&self.inactive) {
+                continue;
+            }
+            if let Some(mut obj) = slot.obj.take() {
+                f(&mut *obj, self);
+                self.slots[index].obj = Some(obj);
+            }
+        }
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=&mut (dyn Object + 'static)> {
+        self.slots.iter_mut()
+            .filter_map(|slot| slot.obj.as_mut())
+            .map(|obj| &mut **obj)
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
     pub fn clear(&mut self) {
-        self.set.clear();
+        let mut alloc = lock(&self.alloc);
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if let Some(obj) = slot.obj.take() {
+                self.dropped.push(obj);
+                slot.tags.clear();
+                slot.generation = slot.generation.wrapping_add(1);
+                alloc.free.push((index as u32, slot.generation));
+            }
+        }
+        drop(alloc);
+        self.draw_order.clear();
+        self.len = 0;
     }
 }
 
-pub struct ObjectId<T: ?Sized>(*const T);
+/// A handle to an object in an `ObjectSet`
+///
+/// Made up of the index of the slot and the generation of the slot
+/// at the time the object was added.
+pub struct ObjectId<T: ?Sized> {
+    index: u32,
+    generation: u32,
+    _marker: PhantomData<fn() -> *const T>,
+}
 
 impl<T: ?Sized> ObjectId<T> {
     #[inline(always)]
-    fn ptr(&self) -> *const T {
-        self.0 as *const T
+    fn new(index: u32, generation: u32) -> Self {
+        ObjectId {
+            index,
+            generation,
+            _marker: PhantomData,
+        }
+    }
+    /// Packs the id into a single number, e.g. for sending or saving it
+    #[inline]
+    pub fn to_bits(self) -> u64 {
+        (u64::from(self.generation) << 32) | u64::from(self.index)
     }
+    /// Unpacks an id made with `to_bits`
+    ///
+    /// The type is checked again when the object is looked up.
+    #[inline]
+    pub fn from_bits(bits: u64) -> Self {
+        Self::new(bits as u32, (bits >> 32) as u32)
+    }
+    /// The index of the slot the object is in
     #[inline(always)]
-    fn n(&self) -> usize {
-        self.ptr() as *const () as usize
+    pub(crate) fn index(self) -> u32 {
+        self.index
+    }
+    /// Forgets the type of the object
+    #[inline]
+    pub fn erase(self) -> ObjectId<dyn Object> {
+        ObjectId::new(self.index, self.generation)
     }
 }
 
 impl<T: ?Sized> Clone for ObjectId<T> {
     #[inline(always)]
     fn clone(&self) -> ObjectId<T> {
-        ObjectId(self.0)
+        *self
     }
 }
 impl<T: ?Sized> Copy for ObjectId<T> {}
 
 impl<T: ?Sized> Hash for ObjectId<T> {
     fn hash<H: Hasher>(&self, h: &mut H) {
-        let mut bytes = [0; ::std::mem::size_of::<usize>()];
-        BigEndian::write_u64(&mut bytes, self.n() as u64);
-        h.write(&bytes)
+        self.to_bits().hash(h)
     }
 }
 
 impl<T: ?Sized, U: ?Sized> PartialEq<ObjectId<U>> for ObjectId<T> {
     fn eq(&self, other: &ObjectId<U>) -> bool {
-        self.n() == other.n()
+        self.index == other.index && self.generation == other.generation
     }
 }
 impl<T: ?Sized> Eq for ObjectId<T> {}
 
 impl<T: ?Sized> Debug for ObjectId<T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "obj@{:p}", self.0)
+        write!(fmt, "obj#{}v{}", self.index, self.generation)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: ?Sized> serde::Serialize for ObjectId<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.to_bits())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de, T: ?Sized> serde::Deserialize<'de> for ObjectId<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(Self::from_bits)
     }
 }
 
 use ggez::{Context, GameResult};
-use super::Textures;
+use crate::collision::{Collider, Collision};
+use crate::physics::BodyMut;
+
+/// Lets objects be downcast to their concrete type
+///
+/// Implemented for every `'static` type.
+pub trait AsAny: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any> AsAny for T {
+    #[inline(always)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    #[inline(always)]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    #[inline(always)]
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
 
-pub trait Object {
-    fn draw(&self, ctx: &mut Context, texes: &Textures) -> GameResult<()>;
-    // Add State ref here
-    fn update(&mut self, ctx: &mut Context, state: &mut State, delta: f32);
+pub trait Object: AsAny {
+    /// Draws the object in the world
+    ///
+    /// `alpha` is how far the time is between the last tick and the next, from 0 to 1,
+    /// for drawing moving objects between where they were and where they are.
+    fn draw(&self, ctx: &mut Context, state: &State, alpha: f32) -> GameResult<()>;
+    /// Draws on top of everything drawn in the world without the camera,
+    /// after all objects have been drawn and before `Game::draw_hud`
+    ///
+    /// Use `state.camera` to place things relative to the object on the screen.
+    #[inline]
+    fn draw_hud(&self, _ctx: &mut Context, _state: &State) -> GameResult<()> { Ok(()) }
+    /// Moves the object forward one tick
+    ///
+    /// `objects` is every other object in the set, this one is taken out while it updates.
+    /// Changes to the set go through `state.commands`.
+    fn update(&mut self, ctx: &mut Context, state: &mut State, objects: &ObjectSet, delta: f32);
+    /// Run every update with the real time since the last one,
+    /// even while the game is paused or slowed down
+    #[inline]
+    fn update_unscaled(&mut self, _ctx: &mut Context, _state: &mut State, _objects: &ObjectSet, _delta: f32) {}
+    /// The shape used to check for collisions with other objects
+    #[inline]
+    fn collider(&self) -> Option<Collider> { None }
+    /// Where the object is, used by the spatial queries on `ObjectSet`
+    /// for objects without bounds
+    #[inline]
+    fn pos(&self) -> Option<Point2> { self.collider().map(|c| c.pos) }
+    /// The area the object covers, used by the spatial queries on `ObjectSet`
+    /// and to tell when it comes into view
+    ///
+    /// Objects that are drawn bigger than their collider, or drawn without one, should give their drawn size.
+    #[inline]
+    fn bounds(&self) -> Option<Rect> { self.collider().map(|c| c.bounds()) }
+    /// The body moving this object, integrated every tick after the objects update
+    #[inline]
+    fn body_mut(&mut self) -> Option<BodyMut<'_>> { None }
+    /// Called after the object updates when it starts, keeps or stops touching another object
+    ///
+    /// `collision.a` is always this object.
+    #[inline]
+    fn on_collision(&mut self, _ctx: &mut Context, _state: &mut State, _collision: &Collision) {}
+    /// Called before the first update after the object is added
+    #[inline]
+    fn on_added(&mut self, _ctx: &mut Context, _state: &mut State) {}
+    /// Called before the next update after the object is removed by the set,
+    /// right before it's dropped
+    ///
+    /// Not called for objects given back by `ObjectSet::remove` and `remove_boxed`.
+    #[inline]
+    fn on_removed(&mut self, _ctx: &mut Context, _state: &mut State) {}
+    /// Called at the end of the tick the object comes into view of the camera
+    #[inline]
+    fn on_enter_view(&mut self, _ctx: &mut Context, _state: &mut State) {}
+    /// Called at the end of the tick the object goes out of view of the camera
+    #[inline]
+    fn on_exit_view(&mut self, _ctx: &mut Context, _state: &mut State) {}
 }
 
 pub mod tex_box;
+pub mod commands;
+pub mod tags;
+pub mod lifecycle;
+
+use self::tags::Tag;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Dummy(u32);
+
+    impl Object for Dummy {
+        fn draw(&self, _: &mut Context, _: &State, _: f32) -> GameResult {
+            Ok(())
+        }
+        fn update(&mut self, _: &mut Context, _: &mut State, _: &ObjectSet, _: f32) {}
+    }
+
+    #[test]
+    fn removed_slots_are_reused_with_a_new_generation() {
+        let mut set = ObjectSet::new();
+        let first = set.add(Dummy(1));
+        assert_eq!(set.remove(first).map(|d| d.0), Some(1));
+
+        let second = set.add(Dummy(2));
+        assert_eq!(second.index(), first.index());
+        assert_ne!(second, first);
+
+        // The old id doesn't reach the object now in its slot
+        assert!(!set.contains(first));
+        assert!(set.get(first).is_none());
+        assert!(set.get_dyn(first).is_none());
+        assert!(set.remove(first).is_none());
+        assert_eq!(set.get(second).map(|d| d.0), Some(2));
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn cleared_ids_stay_stale() {
+        let mut set = ObjectSet::new();
+        let ids: Vec<_> = (0..3).map(|i| set.add(Dummy(i))).collect();
+        set.clear();
+        let again: Vec<_> = (0..3).map(|i| set.add(Dummy(i))).collect();
+        for id in ids {
+            assert!(!set.contains(id));
+            assert!(!again.contains(&id));
+        }
+    }
+
+    #[test]
+    fn commands_add_at_the_id_they_give_out() {
+        let mut set = ObjectSet::new();
+        let mut commands = set.commands();
+        let id = commands.add(Dummy(7));
+        assert!(!set.contains(id));
+        set.apply(&mut commands);
+        assert_eq!(set.get(id).map(|d| d.0), Some(7));
+    }
+
+    #[test]
+    fn dropped_commands_give_their_slots_back() {
+        let mut set = ObjectSet::new();
+        let reserved = {
+            let mut commands = set.commands();
+            commands.add(Dummy(0))
+        };
+        let id = set.add(Dummy(1));
+        assert_eq!(id.index(), reserved.index());
+        // The reserved id was never filled, and can't be mistaken for the new object
+        assert_ne!(id, reserved);
+        assert!(!set.contains(reserved));
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+
+use super::{lock, Allocator, Layer, Object, ObjectId, ObjectSet, Tag};
+
+enum Command {
+    Add {
+        index: u32,
+        generation: u32,
+        layer: Layer,
+        obj: Box<dyn Object>,
+    },
+    Remove(ObjectId<dyn Object>),
+    SetLayer(ObjectId<dyn Object>, Layer),
+    Tag(ObjectId<dyn Object>, Tag),
+}
+
+/// Queued changes to an `ObjectSet`
+///
+/// Used where the set itself can't be borrowed, like in `Object::update`.
+/// The changes are applied with `ObjectSet::apply` in the order they were queued.
+pub struct Commands {
+    alloc: Arc<Mutex<Allocator>>,
+    queue: Vec<Command>,
+}
+
+impl Debug for Commands {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Commands")
+            .field("queued", &self.queue.len())
+            .finish()
+    }
+}
+
+/// Gives back the slots of objects that were never added
+impl Drop for Commands {
+    fn drop(&mut self) {
+        let mut alloc = lock(&self.alloc);
+        for command in &self.queue {
+            if let Command::Add { index, generation, .. } = *command {
+                // The id was handed out, so the slot can't have that generation again
+                alloc.free.push((index, generation.wrapping_add(1)));
+            }
+        }
+    }
+}
+
+impl Commands {
+    /// Queues adding an object in the `WORLD` layer
+    #[inline]
+    pub fn add<O: Object>(&mut self, obj: O) -> ObjectId<O> {
+        self.add_to_layer(obj, Layer::WORLD)
+    }
+    /// Queues adding an object on top of the given layer
+    ///
+    /// The returned id is valid right away,
+    /// but looking it up gives `None` until the commands have been applied.
+    pub fn add_to_layer<O: Object>(&mut self, obj: O, layer: Layer) -> ObjectId<O> {
+        let (index, generation) = lock(&self.alloc).allocate();
+        self.queue.push(Command::Add {
+            index,
+            generation,
+            layer,
+            obj: Box::new(obj),
+        });
+        ObjectId::new(index, generation)
+    }
+    /// Queues removing an object
+    #[inline]
+    pub fn remove<T: ?Sized>(&mut self, id: ObjectId<T>) {
+        self.queue.push(Command::Remove(id.erase()));
+    }
+    /// Queues moving an object to the top of the given layer
+    #[inline]
+    pub fn set_layer<T: ?Sized>(&mut self, id: ObjectId<T>, layer: Layer) {
+        self.queue.push(Command::SetLayer(id.erase(), layer));
+    }
+    /// Queues putting a tag on an object
+    #[inline]
+    pub fn tag<T: ?Sized, G: Into<Tag>>(&mut self, id: ObjectId<T>, tag: G) {
+        self.queue.push(Command::Tag(id.erase(), tag.into()));
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+impl ObjectSet {
+    /// Makes a new command queue for this set
+    pub fn commands(&self) -> Commands {
+        Commands {
+            alloc: Arc::clone(&self.alloc),
+            queue: Vec::new(),
+        }
+    }
+    /// Applies and empties the queued commands
+    pub fn apply(&mut self, commands: &mut Commands) {
+        debug_assert!(Arc::ptr_eq(&self.alloc, &commands.alloc), "commands belong to another set");
+
+        for command in commands.queue.drain(..) {
+            match command {
+                Command::Add { index, generation, layer, obj } => {
+                    self.insert(index, generation, layer, obj);
+                }
+                Command::Remove(id) => {
+                    self.discard(id);
+                }
+                Command::SetLayer(id, layer) => {
+                    self.set_layer(id, layer);
+                }
+                Command::Tag(id, tag) => {
+                    self.tag(id, tag);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Telling objects when they're added, removed and seen
+use std::collections::HashSet;
+use std::mem;
+
+use ggez::Context;
+use ggez::graphics::Rect;
+
+use super::{Object, ObjectId, ObjectSet};
+use crate::State;
+
+/// How many times the hooks can add or remove more objects before the rest waits for the next tick
+const MAX_LIFECYCLE_ROUNDS: u32 = 16;
+
+impl ObjectSet {
+    /// Removes an object and keeps it around until its `on_removed` has been run
+    pub(crate) fn discard<T: ?Sized>(&mut self, id: ObjectId<T>) -> bool {
+        match self.remove_boxed(id) {
+            Some(obj) => {
+                self.dropped.push(obj);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Runs `on_added` and `on_removed` for the objects added and dropped since the last time,
+    /// until the hooks stop adding and removing objects
+    ///
+    /// Hooks that keep adding or removing objects are cut off after a few rounds,
+    /// and the objects from the last round are told on the next tick.
+    pub(crate) fn run_lifecycle(&mut self, ctx: &mut Context, state: &mut State) {
+        self.run_lifecycle_with(state, |obj, added, state| if added {
+            obj.on_added(ctx, state)
+        } else {
+            obj.on_removed(ctx, state)
+        })
+    }
+    /// Does the rounds of `run_lifecycle`, calling `hook` on each object
+    /// with whether it was added (`true`) or removed (`false`)
+    fn run_lifecycle_with<F>(&mut self, state: &mut State, mut hook: F)
+    where F: FnMut(&mut dyn Object, bool, &mut State) {
+        for _ in 0..MAX_LIFECYCLE_ROUNDS {
+            if self.added.is_empty() && self.dropped.is_empty() {
+                return;
+            }
+            for id in mem::take(&mut self.added) {
+                // It may have been removed again before it was told it was added
+                if let Some(obj) = self.get_dyn_mut(id) {
+                    hook(obj, true, state);
+                }
+            }
+            for mut obj in mem::take(&mut self.dropped) {
+                hook(&mut *obj, false, state);
+            }
+            self.apply(&mut state.commands);
+        }
+        if !self.added.is_empty() || !self.dropped.is_empty() {
+            warn!("Objects kept being added or removed by on_added and on_removed, the rest waits for the next tick");
+        }
+    }
+    /// Runs `on_enter_view` and `on_exit_view` for the objects whose bounds
+    /// went into or out of `view` since the last time
+    ///
+    /// Uses the spatial index, so objects without bounds or a position are never in view.
+    pub(crate) fn run_view_hooks(&mut self, ctx: &mut Context, state: &mut State, view: Rect) {
+        let seen = self.query_rect(view);
+        let seen_set: HashSet<_> = seen.iter().copied().collect();
+        let mut changed: Vec<(ObjectId<dyn Object>, bool)> = seen.iter()
+            .filter(|id| !self.slots[id.index as usize].in_view)
+            .map(|&id| (id, true))
+            .collect();
+        changed.extend(self.iter_with_ids()
+            .map(|(id, _)| id)
+            .filter(|id| self.slots[id.index as usize].in_view && !seen_set.contains(id))
+            .map(|id| (id, false)));
+
+        for (id, in_view) in changed {
+            self.slots[id.index as usize].in_view = in_view;
+            if let Some(obj) = self.get_dyn_mut(id) {
+                if in_view {
+                    obj.on_enter_view(ctx, state);
+                } else {
+                    obj.on_exit_view(ctx, state);
+                }
+            }
+        }
+        self.apply(&mut state.commands);
+    }
+    /// Whether the object was in view of the camera at the end of the last tick
+    #[inline]
+    pub fn in_view<T: ?Sized>(&self, id: ObjectId<T>) -> bool {
+        self.contains(id) && self.slots[id.index as usize].in_view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use ggez::GameResult;
+
+    use super::*;
+    use crate::textures::Textures;
+
+    fn state<'a>(object_set: &ObjectSet) -> State<'a> {
+        State::with_size(800., 600., object_set, 60, Textures::without_assets())
+    }
+
+    /// Counts how many times it was added and removed
+    #[derive(Default)]
+    struct Counts {
+        added: Cell<u32>,
+        removed: Cell<u32>,
+    }
+
+    struct Tracked(Rc<Counts>);
+
+    impl Object for Tracked {
+        fn draw(&self, _: &mut Context, _: &State, _: f32) -> GameResult {
+            Ok(())
+        }
+        fn update(&mut self, _: &mut Context, _: &mut State, _: &ObjectSet, _: f32) {}
+    }
+
+    /// Counts the hooks run on `Tracked` objects
+    fn count(obj: &mut dyn Object, added: bool, _: &mut State) {
+        let counts = &obj.as_any().downcast_ref::<Tracked>().unwrap().0;
+        let count = if added { &counts.added } else { &counts.removed };
+        count.set(count.get() + 1);
+    }
+
+    /// Adds another of itself when it's added, forever
+    struct Breeder;
+
+    impl Object for Breeder {
+        fn draw(&self, _: &mut Context, _: &State, _: f32) -> GameResult {
+            Ok(())
+        }
+        fn update(&mut self, _: &mut Context, _: &mut State, _: &ObjectSet, _: f32) {}
+    }
+
+    #[test]
+    fn hooks_run_once_for_added_and_removed_objects() {
+        let mut set = ObjectSet::new();
+        let mut state = state(&set);
+        let counts: Vec<_> = (0..3).map(|_| Rc::new(Counts::default())).collect();
+        let ids: Vec<_> = counts.iter().map(|c| state.commands.add(Tracked(c.clone()))).collect();
+        state.commands.tag(ids[1], "tagged");
+        set.apply(&mut state.commands);
+        set.run_lifecycle_with(&mut state, count);
+        set.run_lifecycle_with(&mut state, count);
+        assert!(counts.iter().all(|c| c.added.get() == 1 && c.removed.get() == 0));
+
+        state.commands.remove(ids[0]);
+        // Removing it twice doesn't tell it twice
+        state.commands.remove(ids[0]);
+        set.apply(&mut state.commands);
+        assert_eq!(set.remove_tagged("tagged"), 1);
+        set.run_lifecycle_with(&mut state, count);
+        assert_eq!(counts[0].removed.get(), 1);
+        assert_eq!(counts[1].removed.get(), 1);
+        assert_eq!(counts[2].removed.get(), 0);
+
+        set.clear();
+        set.run_lifecycle_with(&mut state, count);
+        set.run_lifecycle_with(&mut state, count);
+        assert!(counts.iter().all(|c| c.added.get() == 1 && c.removed.get() == 1));
+    }
+
+    #[test]
+    fn objects_removed_before_their_hook_are_only_told_they_were_removed() {
+        let mut set = ObjectSet::new();
+        let mut state = state(&set);
+        let counts = Rc::new(Counts::default());
+        let id = set.add(Tracked(counts.clone()));
+        set.discard(id);
+        set.run_lifecycle_with(&mut state, count);
+        assert_eq!((counts.added.get(), counts.removed.get()), (0, 1));
+    }
+
+    #[test]
+    fn hooks_that_keep_adding_are_cut_off() {
+        let mut set = ObjectSet::new();
+        let mut state = state(&set);
+        let breed = |obj: &mut dyn Object, added: bool, state: &mut State| if added && obj.as_any().is::<Breeder>() {
+            state.commands.add(Breeder);
+        };
+        set.add(Breeder);
+        set.run_lifecycle_with(&mut state, breed);
+        let after_one = set.count_of::<Breeder>();
+        assert_eq!(after_one, 1 + MAX_LIFECYCLE_ROUNDS as usize);
+        // The ones added in the last round are told next time
+        set.run_lifecycle_with(&mut state, breed);
+        assert_eq!(set.count_of::<Breeder>(), after_one + MAX_LIFECYCLE_ROUNDS as usize);
+    }
+}
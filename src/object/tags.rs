@@ -0,0 +1,131 @@
+use std::borrow::{Borrow, Cow};
+use std::fmt::{self, Display};
+
+use super::{Object, ObjectId, ObjectSet};
+
+/// A label put on objects to group them
+///
+/// Can be made from strings, or from your own enum by implementing `From` for it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tag(Cow<'static, str>);
+
+impl Tag {
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&'static str> for Tag {
+    #[inline]
+    fn from(s: &'static str) -> Self {
+        Tag(Cow::Borrowed(s))
+    }
+}
+impl From<String> for Tag {
+    #[inline]
+    fn from(s: String) -> Self {
+        Tag(Cow::Owned(s))
+    }
+}
+impl Borrow<str> for Tag {
+    #[inline]
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+impl Display for Tag {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(fmt)
+    }
+}
+
+impl ObjectSet {
+    /// Puts a tag on an object
+    ///
+    /// Returns `false` if the object doesn't exist.
+    pub fn tag<T: ?Sized, G: Into<Tag>>(&mut self, id: ObjectId<T>, tag: G) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        let tag = tag.into();
+        let tags = &mut self.slots[id.index as usize].tags;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+        true
+    }
+    /// Takes a tag off an object
+    pub fn untag<T: ?Sized>(&mut self, id: ObjectId<T>, tag: &str) {
+        if self.contains(id) {
+            self.slots[id.index as usize].tags.retain(|t| t.as_str() != tag);
+        }
+    }
+    #[inline]
+    pub fn has_tag<T: ?Sized>(&self, id: ObjectId<T>, tag: &str) -> bool {
+        self.tags(id).iter().any(|t| t.as_str() == tag)
+    }
+    /// Gets the tags on an object
+    pub fn tags<T: ?Sized>(&self, id: ObjectId<T>) -> &[Tag] {
+        if self.contains(id) {
+            &self.slots[id.index as usize].tags
+        } else {
+            &[]
+        }
+    }
+    /// Iterates over all objects with the given tag
+    pub fn iter_tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item=(ObjectId<dyn Object>, &'a dyn Object)> {
+        self.slots.iter()
+            .enumerate()
+            .filter(move |(_, slot)| slot.tags.iter().any(|t| t.as_str() == tag))
+            .filter_map(|(index, slot)| {
+                let obj = slot.obj.as_deref()?;
+                Some((ObjectId::new(index as u32, slot.generation), obj))
+            })
+    }
+    /// Iterates mutably over all objects with the given tag
+    pub fn iter_tagged_mut<'a>(&'a mut self, tag: &'a str) -> impl Iterator<Item=(ObjectId<dyn Object>, &'a mut (dyn Object + 'static))> {
+        self.slots.iter_mut()
+            .enumerate()
+            .filter(move |(_, slot)| slot.tags.iter().any(|t| t.as_str() == tag))
+            .filter_map(|(index, slot)| {
+                let generation = slot.generation;
+                let obj = slot.obj.as_deref_mut()?;
+                Some((ObjectId::new(index as u32, generation), obj))
+            })
+    }
+    /// Removes every object with the given tag and returns how many there were
+    pub fn remove_tagged(&mut self, tag: &str) -> usize {
+        let ids: Vec<_> = self.iter_tagged(tag).map(|(id, _)| id).collect();
+        for &id in &ids {
+            self.discard(id);
+        }
+        ids.len()
+    }
+    /// Sets whether objects with the given tag are updated
+    pub fn set_group_active<G: Into<Tag>>(&mut self, tag: G, active: bool) {
+        let tag = tag.into();
+        if active {
+            self.inactive.remove(&tag);
+        } else {
+            self.inactive.insert(tag);
+        }
+    }
+    /// Sets whether objects with the given tag are drawn
+    pub fn set_group_visible<G: Into<Tag>>(&mut self, tag: G, visible: bool) {
+        let tag = tag.into();
+        if visible {
+            self.hidden.remove(&tag);
+        } else {
+            self.hidden.insert(tag);
+        }
+    }
+    #[inline]
+    pub fn is_group_active(&self, tag: &str) -> bool {
+        !self.inactive.contains(tag)
+    }
+    #[inline]
+    pub fn is_group_visible(&self, tag: &str) -> bool {
+        !self.hidden.contains(tag)
+    }
+}
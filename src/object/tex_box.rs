@@ -2,9 +2,11 @@ use crate::State;
 use std::fmt::Debug;
 use ggez::{graphics::{self, DrawParam}, Context, GameResult};
 
-use crate::{util::Point2, Textures};
+use crate::util::Point2;
+use crate::collision::{Collider, Shape};
+use crate::physics::{Body, BodyMut};
 
-use super::Object;
+use super::{Object, ObjectSet};
 
 #[derive(Debug, Clone)]
 pub struct TexBoxData<'a> {
@@ -13,31 +15,84 @@ pub struct TexBoxData<'a> {
     pub rot: f32,
 }
 
+/// Run by a `TexBox` every tick with its data
+pub type UpdateFn = Box<dyn FnMut(&mut TexBoxData, &mut Context, &mut State, &ObjectSet, f32)>;
+
 pub struct TexBox<'a> {
     pub data: TexBoxData<'a>,
-    update_fn: Box<dyn FnMut(&mut TexBoxData, &mut Context, &mut State, f32)>
+    /// The shape to collide with, placed at `data.pos` and rotated by `data.rot`
+    pub shape: Option<Shape>,
+    /// Moves `data.pos` and `data.rot` every tick if set
+    body: Option<Body>,
+    /// The position and rotation before the last tick
+    prev: (Point2, f32),
+    update_fn: UpdateFn,
 }
 
 impl<'a> TexBox<'a> {
-    pub fn new<F: 'static + FnMut(&mut TexBoxData, &mut Context, &mut State, f32)>(data: TexBoxData<'a>, update: F) -> Self {
+    pub fn new<F: 'static + FnMut(&mut TexBoxData, &mut Context, &mut State, &ObjectSet, f32)>(data: TexBoxData<'a>, update: F) -> Self {
         TexBox {
+            prev: (data.pos, data.rot),
             data,
+            shape: None,
+            body: None,
             update_fn: Box::new(update),
         }
     }
+    #[inline]
+    pub fn with_collider(self, shape: Shape) -> Self {
+        TexBox {
+            shape: Some(shape),
+            .. self
+        }
+    }
+    /// Gives the box a body that moves it every tick
+    ///
+    /// Change the body through `Object::body_mut`.
+    #[inline]
+    pub fn with_body(self, body: Body) -> Self {
+        TexBox {
+            body: Some(body),
+            .. self
+        }
+    }
+    #[inline]
+    pub fn body(&self) -> Option<&Body> {
+        self.body.as_ref()
+    }
+    /// The position and rotation `alpha` of the way from before the last tick to now
+    fn interpolated(&self, alpha: f32) -> (Point2, f32) {
+        let (prev_pos, prev_rot) = self.prev;
+        (prev_pos + (self.data.pos - prev_pos) * alpha, prev_rot + (self.data.rot - prev_rot) * alpha)
+    }
 }
 
 impl Object for TexBox<'static> {
-    fn update(&mut self, ctx: &mut Context, state: &mut State, delta: f32) {
-        (self.update_fn)(&mut self.data, ctx, state, delta)
+    fn update(&mut self, ctx: &mut Context, state: &mut State, objects: &ObjectSet, delta: f32) {
+        self.prev = (self.data.pos, self.data.rot);
+        (self.update_fn)(&mut self.data, ctx, state, objects, delta)
     }
     #[inline]
-    fn draw(&self, ctx: &mut Context, t: &Textures) -> GameResult<()> {
-        let img = t.get_img(ctx, &self.data.texture);
+    fn pos(&self) -> Option<Point2> {
+        Some(self.data.pos)
+    }
+    #[inline]
+    fn body_mut(&mut self) -> Option<BodyMut<'_>> {
+        let TexBoxData { pos, rot, .. } = &mut self.data;
+        self.body.as_mut().map(move |body| BodyMut { body, pos, rot })
+    }
+    #[inline]
+    fn collider(&self) -> Option<Collider> {
+        self.shape.map(|shape| Collider::new(self.data.pos, shape).rotated(self.data.rot))
+    }
+    #[inline]
+    fn draw(&self, ctx: &mut Context, state: &State, alpha: f32) -> GameResult<()> {
+        let img = state.textures.get_img(ctx, self.data.texture);
 
+        let (pos, rot) = self.interpolated(alpha);
         let drawparams = DrawParam {
-            dest: self.data.pos.into(),
-            rotation: self.data.rot,
+            dest: pos.into(),
+            rotation: rot,
             offset: Point2::new(0.5, 0.5).into(),
             .. Default::default()
         };
@@ -45,3 +100,27 @@ impl Object for TexBox<'static> {
         graphics::draw(ctx, &*img, drawparams)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drawn_between_the_last_two_ticks() {
+        let data = TexBoxData {
+            texture: "box",
+            pos: Point2::new(10., 20.),
+            rot: 1.,
+        };
+        let mut tex_box = TexBox::new(data, |_, _, _, _, _| ());
+        assert_eq!(tex_box.interpolated(0.), tex_box.interpolated(1.));
+
+        // What `update` does before moving the box
+        tex_box.prev = (tex_box.data.pos, tex_box.data.rot);
+        tex_box.data.pos = Point2::new(30., 0.);
+        tex_box.data.rot = 2.;
+        assert_eq!(tex_box.interpolated(0.), (Point2::new(10., 20.), 1.));
+        assert_eq!(tex_box.interpolated(1.), (Point2::new(30., 0.), 2.));
+        assert_eq!(tex_box.interpolated(0.5), (Point2::new(20., 10.), 1.5));
+    }
+}
@@ -0,0 +1,145 @@
+//! Simple rigid bodies moved every tick
+use crate::util::{Vector2, Point2};
+use crate::collision::{self, Contact, Contacts};
+use crate::object::{Object, ObjectId, ObjectSet};
+
+/// How a body is moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BodyKind {
+    /// Never moves, like objects with a collider but no body
+    Static,
+    /// Moved only by its velocity, collisions don't push it
+    Kinematic,
+    /// Moved by its velocity and pushed out of what it collides with
+    Dynamic,
+}
+
+/// The movement of an object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Body {
+    pub kind: BodyKind,
+    pub vel: Vector2,
+    pub acc: Vector2,
+    pub ang_vel: f32,
+    /// How much of the velocity is lost per second
+    pub damping: f32,
+    /// How much of the angular velocity is lost per second
+    pub angular_damping: f32,
+    pub mass: f32,
+    /// How bouncy the body is, 0 stops dead and 1 bounces back at full speed
+    pub restitution: f32,
+}
+
+/// A body along with the position and rotation of the object it moves
+#[derive(Debug)]
+pub struct BodyMut<'a> {
+    pub body: &'a mut Body,
+    pub pos: &'a mut Point2,
+    pub rot: &'a mut f32,
+}
+
+impl Body {
+    fn with_kind(kind: BodyKind) -> Self {
+        Body {
+            kind,
+            vel: Vector2::zeros(),
+            acc: Vector2::zeros(),
+            ang_vel: 0.,
+            damping: 0.,
+            angular_damping: 0.,
+            mass: 1.,
+            restitution: 0.,
+        }
+    }
+    #[inline]
+    pub fn fixed() -> Self {
+        Self::with_kind(BodyKind::Static)
+    }
+    #[inline]
+    pub fn kinematic() -> Self {
+        Self::with_kind(BodyKind::Kinematic)
+    }
+    #[inline]
+    pub fn dynamic(mass: f32) -> Self {
+        Body {
+            mass,
+            .. Self::with_kind(BodyKind::Dynamic)
+        }
+    }
+    /// One over the mass, or 0 if the body can't be pushed
+    #[inline]
+    pub fn inv_mass(&self) -> f32 {
+        match self.kind {
+            BodyKind::Dynamic if self.mass > 0. => 1. / self.mass,
+            _ => 0.,
+        }
+    }
+    /// Changes the velocity as if hit by the given impulse
+    #[inline]
+    pub fn apply_impulse(&mut self, impulse: Vector2) {
+        self.vel += impulse * self.inv_mass();
+    }
+    /// Moves the body forward in time
+    pub fn integrate(&mut self, pos: &mut Point2, rot: &mut f32, delta: f32) {
+        if self.kind == BodyKind::Static {
+            return;
+        }
+        self.vel += self.acc * delta;
+        self.vel *= (1. - self.damping * delta).max(0.);
+        self.ang_vel *= (1. - self.angular_damping * delta).max(0.);
+
+        *pos += self.vel * delta;
+        *rot += self.ang_vel * delta;
+    }
+}
+
+/// Copies out the body of an object, treating objects without one as static
+fn body_of(object_set: &mut ObjectSet, id: ObjectId<dyn Object>) -> Body {
+    object_set.get_dyn_mut(id)
+        .and_then(|obj| obj.body_mut().map(|b| *b.body))
+        .unwrap_or_else(Body::fixed)
+}
+
+fn push(object_set: &mut ObjectSet, id: ObjectId<dyn Object>, dir: Vector2, distance: f32, speed: f32) {
+    if let Some(BodyMut { body, pos, .. }) = object_set.get_dyn_mut(id).and_then(|obj| obj.body_mut()) {
+        *pos += dir * distance;
+        body.vel += dir * speed;
+    }
+}
+
+/// Pushes two overlapping objects apart and bounces them off each other
+fn resolve(object_set: &mut ObjectSet, a: ObjectId<dyn Object>, b: ObjectId<dyn Object>, contact: Contact) {
+    let (body_a, body_b) = (body_of(object_set, a), body_of(object_set, b));
+    let (inv_a, inv_b) = (body_a.inv_mass(), body_b.inv_mass());
+    let total = inv_a + inv_b;
+    if total == 0. {
+        return;
+    }
+
+    let n = contact.normal;
+    let approach = (body_b.vel - body_a.vel).dot(&n);
+    let impulse = if approach < 0. {
+        -(1. + body_a.restitution.max(body_b.restitution)) * approach / total
+    } else {
+        0.
+    };
+
+    push(object_set, a, -n, contact.depth * inv_a / total, impulse * inv_a);
+    push(object_set, b, n, contact.depth * inv_b / total, impulse * inv_b);
+}
+
+/// Moves all bodies of active objects and resolves collisions between them
+///
+/// Returns the contacts found after moving, from before they were resolved.
+pub fn step(object_set: &mut ObjectSet, delta: f32) -> Contacts {
+    for obj in object_set.iter_active_mut() {
+        if let Some(BodyMut { body, pos, rot }) = obj.body_mut() {
+            body.integrate(pos, rot, delta);
+        }
+    }
+    let contacts = collision::contacts(object_set);
+    for &(a, b, contact) in &contacts {
+        resolve(object_set, a, b, contact);
+    }
+    contacts
+}
@@ -0,0 +1,330 @@
+//! Recording input events and playing them back
+//!
+//! A recording is every input event along with the tick it came before.
+//! Since every tick is equally long, playing it back from the same starting point
+//! runs the same handlers on the same ticks and reproduces the run.
+//!
+//! Recordings are stored in a small binary format, little endian:
+//!
+//! - the magic bytes `KNDR` and a version byte
+//! - the number of events as a `u32`
+//! - for each event, the number of ticks since the event before it as a LEB128 number,
+//!   a byte telling what kind of event it is and then its fields
+//!
+//! Keys, pads, gamepad buttons and axes are stored as LEB128 numbers as well.
+use std::convert::TryFrom;
+use std::io::{self, Read, Write, BufReader, BufWriter};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ggez::{Context, GameError, GameResult, filesystem, event::{KeyMods, MouseButton}};
+
+use crate::util::Point2;
+use crate::bindings::key_from_code;
+use crate::input::{InputEvent, PadEvent, Pad, button_from_code, axis_from_code};
+
+const MAGIC: &[u8; 4] = b"KNDR";
+const VERSION: u8 = 2;
+
+/// Input events and the ticks they came before, counted from the start of the recording
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub events: Vec<(u64, InputEvent)>,
+}
+
+fn load_error<S: Into<String>>(s: S) -> GameError {
+    GameError::ResourceLoadError(s.into())
+}
+
+fn write_varint<W: Write>(w: &mut W, mut n: u64) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return w.write_u8(byte);
+        }
+        w.write_u8(byte | 0x80)?;
+    }
+}
+
+fn read_varint<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = r.read_u8()?;
+        n |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(n)
+}
+
+/// Reads a LEB128 number that has to fit in `T`
+fn read_code<R: Read, T: TryFrom<u64>>(r: &mut R) -> GameResult<T> {
+    let n = read_varint(r)?;
+    T::try_from(n).map_err(|_| load_error(format!("Code {} is out of range in recording", n)))
+}
+
+fn write_point<W: Write>(w: &mut W, p: Point2) -> io::Result<()> {
+    w.write_f32::<LittleEndian>(p.x)?;
+    w.write_f32::<LittleEndian>(p.y)
+}
+
+fn read_point<R: Read>(r: &mut R) -> io::Result<Point2> {
+    let x = r.read_f32::<LittleEndian>()?;
+    let y = r.read_f32::<LittleEndian>()?;
+    Ok(Point2::new(x, y))
+}
+
+fn write_button<W: Write>(w: &mut W, button: MouseButton) -> io::Result<()> {
+    match button {
+        MouseButton::Left => w.write_u8(0),
+        MouseButton::Right => w.write_u8(1),
+        MouseButton::Middle => w.write_u8(2),
+        MouseButton::Other(n) => {
+            w.write_u8(3)?;
+            w.write_u8(n)
+        }
+    }
+}
+
+fn read_button<R: Read>(r: &mut R) -> GameResult<MouseButton> {
+    Ok(match r.read_u8()? {
+        0 => MouseButton::Left,
+        1 => MouseButton::Right,
+        2 => MouseButton::Middle,
+        3 => MouseButton::Other(r.read_u8()?),
+        n => return Err(load_error(format!("Unknown mouse button {} in recording", n))),
+    })
+}
+
+impl Recording {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Writes the recording in the recording format
+    ///
+    /// The events have to be in the order of their ticks.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> GameResult {
+        w.write_all(MAGIC)?;
+        w.write_u8(VERSION)?;
+        let len = u32::try_from(self.events.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many events to record"))?;
+        w.write_u32::<LittleEndian>(len)?;
+
+        let mut last_tick = 0;
+        for &(tick, event) in &self.events {
+            let gap = tick.checked_sub(last_tick)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Recorded events are out of order"))?;
+            write_varint(w, gap)?;
+            last_tick = tick;
+
+            match event {
+                InputEvent::KeyDown { key, mods, repeat } => {
+                    w.write_u8(0)?;
+                    write_varint(w, key as u64)?;
+                    w.write_u8(mods.bits())?;
+                    w.write_u8(repeat as u8)?;
+                }
+                InputEvent::KeyUp { key, mods } => {
+                    w.write_u8(1)?;
+                    write_varint(w, key as u64)?;
+                    w.write_u8(mods.bits())?;
+                }
+                InputEvent::MouseDown { button, pos } => {
+                    w.write_u8(2)?;
+                    write_button(w, button)?;
+                    write_point(w, pos)?;
+                }
+                InputEvent::MouseUp { button, pos } => {
+                    w.write_u8(3)?;
+                    write_button(w, button)?;
+                    write_point(w, pos)?;
+                }
+                InputEvent::MouseMotion { pos } => {
+                    w.write_u8(4)?;
+                    write_point(w, pos)?;
+                }
+                InputEvent::Wheel { x, y } => {
+                    w.write_u8(5)?;
+                    write_point(w, Point2::new(x, y))?;
+                }
+                InputEvent::Pad(PadEvent::Connected(Pad(pad))) => {
+                    w.write_u8(6)?;
+                    write_varint(w, pad as u64)?;
+                }
+                InputEvent::Pad(PadEvent::Disconnected(Pad(pad))) => {
+                    w.write_u8(7)?;
+                    write_varint(w, pad as u64)?;
+                }
+                InputEvent::Pad(PadEvent::ButtonDown(Pad(pad), button)) => {
+                    w.write_u8(8)?;
+                    write_varint(w, pad as u64)?;
+                    write_varint(w, button as u64)?;
+                }
+                InputEvent::Pad(PadEvent::ButtonUp(Pad(pad), button)) => {
+                    w.write_u8(9)?;
+                    write_varint(w, pad as u64)?;
+                    write_varint(w, button as u64)?;
+                }
+                InputEvent::Pad(PadEvent::Axis(Pad(pad), axis, value)) => {
+                    w.write_u8(10)?;
+                    write_varint(w, pad as u64)?;
+                    write_varint(w, axis as u64)?;
+                    w.write_f32::<LittleEndian>(value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Reads a recording in the recording format
+    pub fn read_from<R: Read>(r: &mut R) -> GameResult<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(load_error("Not a recording"));
+        }
+        let version = r.read_u8()?;
+        if version != VERSION {
+            return Err(load_error(format!("Unsupported recording version {}", version)));
+        }
+        let len = r.read_u32::<LittleEndian>()?;
+
+        let key = |code: u32| key_from_code(code)
+            .ok_or_else(|| load_error(format!("Unknown key {} in recording", code)));
+        let mods = |bits: u8| KeyMods::from_bits_truncate(bits);
+        let button = |code: u16| button_from_code(code)
+            .ok_or_else(|| load_error(format!("Unknown gamepad button {} in recording", code)));
+        let axis = |code: u16| axis_from_code(code)
+            .ok_or_else(|| load_error(format!("Unknown gamepad axis {} in recording", code)));
+
+        // The length could be anything in a broken file, so don't trust it too far up front
+        let mut events = Vec::with_capacity((len as usize).min(1 << 16));
+        let mut tick: u64 = 0;
+        for _ in 0..len {
+            tick = tick.checked_add(read_varint(r)?).ok_or_else(|| load_error("Tick overflows in recording"))?;
+            let event = match r.read_u8()? {
+                0 => InputEvent::KeyDown {
+                    key: key(read_code(r)?)?,
+                    mods: mods(r.read_u8()?),
+                    repeat: r.read_u8()? != 0,
+                },
+                1 => InputEvent::KeyUp {
+                    key: key(read_code(r)?)?,
+                    mods: mods(r.read_u8()?),
+                },
+                2 => InputEvent::MouseDown {
+                    button: read_button(r)?,
+                    pos: read_point(r)?,
+                },
+                3 => InputEvent::MouseUp {
+                    button: read_button(r)?,
+                    pos: read_point(r)?,
+                },
+                4 => InputEvent::MouseMotion {
+                    pos: read_point(r)?,
+                },
+                5 => {
+                    let p = read_point(r)?;
+                    InputEvent::Wheel { x: p.x, y: p.y }
+                }
+                6 => InputEvent::Pad(PadEvent::Connected(Pad(read_code(r)?))),
+                7 => InputEvent::Pad(PadEvent::Disconnected(Pad(read_code(r)?))),
+                8 => {
+                    let pad = Pad(read_code(r)?);
+                    InputEvent::Pad(PadEvent::ButtonDown(pad, button(read_code(r)?)?))
+                }
+                9 => {
+                    let pad = Pad(read_code(r)?);
+                    InputEvent::Pad(PadEvent::ButtonUp(pad, button(read_code(r)?)?))
+                }
+                10 => {
+                    let pad = Pad(read_code(r)?);
+                    let axis = axis(read_code(r)?)?;
+                    InputEvent::Pad(PadEvent::Axis(pad, axis, r.read_f32::<LittleEndian>()?))
+                }
+                n => return Err(load_error(format!("Unknown event kind {} in recording", n))),
+            };
+            events.push((tick, event));
+        }
+
+        Ok(Recording { events })
+    }
+    /// Saves the recording to `path` in the user config directory, like `State::save_bindings`
+    ///
+    /// That's the only directory ggez writes to, not the user data directory.
+    pub fn save(&self, ctx: &mut Context, path: &str) -> GameResult {
+        let mut w = BufWriter::new(filesystem::create(ctx, path)?);
+        self.write_to(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+    /// Loads a recording from `path` in the ggez filesystem, like `State::load_bindings`
+    ///
+    /// The resource directories are searched before the user config directory `save` writes to,
+    /// so a recording there is shadowed by one of the same name in the resources.
+    pub fn load(ctx: &mut Context, path: &str) -> GameResult<Self> {
+        let file = filesystem::open(ctx, path)?;
+        Self::read_from(&mut BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggez::event::{Axis, Button, KeyCode};
+
+    #[test]
+    fn written_recordings_read_back_the_same() {
+        let recording = Recording {
+            events: vec![
+                (0, InputEvent::KeyDown { key: KeyCode::Z, mods: KeyMods::SHIFT, repeat: false }),
+                (0, InputEvent::KeyDown { key: KeyCode::Z, mods: KeyMods::SHIFT, repeat: true }),
+                (3, InputEvent::KeyUp { key: KeyCode::Cut, mods: KeyMods::empty() }),
+                (200, InputEvent::MouseDown { button: MouseButton::Other(7), pos: Point2::new(1.5, -2.) }),
+                (201, InputEvent::MouseUp { button: MouseButton::Left, pos: Point2::new(0., 0.) }),
+                (201, InputEvent::MouseMotion { pos: Point2::new(640., 480.) }),
+                (300, InputEvent::Wheel { x: 0., y: -1. }),
+                (1 << 40, InputEvent::Pad(PadEvent::Connected(Pad(300)))),
+                (1 << 40, InputEvent::Pad(PadEvent::ButtonDown(Pad(300), Button::South))),
+                (1 << 40, InputEvent::Pad(PadEvent::ButtonUp(Pad(300), Button::South))),
+                (1 << 41, InputEvent::Pad(PadEvent::Axis(Pad(300), Axis::LeftStickX, -0.25))),
+                (1 << 41, InputEvent::Pad(PadEvent::Disconnected(Pad(300)))),
+            ],
+        };
+        let mut bytes = Vec::new();
+        recording.write_to(&mut bytes).unwrap();
+        assert_eq!(Recording::read_from(&mut &bytes[..]).unwrap(), recording);
+    }
+
+    #[test]
+    fn events_out_of_order_are_not_written() {
+        let event = InputEvent::MouseMotion { pos: Point2::new(0., 0.) };
+        let recording = Recording {
+            events: vec![(5, event), (4, event)],
+        };
+        assert!(recording.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn broken_recordings_are_errors() {
+        let mut bytes = Vec::new();
+        Recording::new().write_to(&mut bytes).unwrap();
+        assert_eq!(Recording::read_from(&mut &bytes[..]).unwrap(), Recording::new());
+
+        // Claims far more events than there are
+        bytes[5..9].copy_from_slice(&u32::max_value().to_le_bytes());
+        assert!(Recording::read_from(&mut &bytes[..]).is_err());
+
+        assert!(Recording::read_from(&mut &b"nope"[..]).is_err());
+
+        // The ticks between events add up past the last tick there can be
+        let space = InputEvent::KeyDown { key: KeyCode::Space, mods: KeyMods::empty(), repeat: false };
+        let mut bytes = Vec::new();
+        Recording { events: vec![(1, space), (2, space)] }.write_to(&mut bytes).unwrap();
+        let mut overflowing = bytes[..9].to_vec();
+        write_varint(&mut overflowing, u64::max_value()).unwrap();
+        overflowing.extend_from_slice(&bytes[10..]);
+        assert!(Recording::read_from(&mut &overflowing[..]).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Scenes stacked on top of the game, like a title screen or a pause menu
+//!
+//! The `Game` is always at the bottom of the stack. Scenes are pushed, popped and replaced
+//! from handlers through `State`, and the changes are made before the next tick.
+use std::fmt::{self, Debug};
+use std::mem;
+
+use ggez::{Context, GameResult};
+use ggez::graphics::{Color, Rect};
+
+use crate::State;
+use crate::util::Vector2;
+use crate::collision::{Collision, CollisionTracker};
+use crate::object::{ObjectSet, commands::Commands};
+use crate::physics;
+
+/// A part of the game with its own objects, like a menu
+///
+/// A scene gets the actions of its input context (if it has one) once it's on the stack.
+pub trait Scene {
+    /// The input context pushed while the scene is on the stack
+    fn context(&self) -> Option<&'static str> { None }
+    /// Whether the scenes below (and the `Game`) still run their ticks
+    fn update_below(&self) -> bool { false }
+    /// Whether the scenes below (and the `Game`) are still drawn under this one
+    fn draw_below(&self) -> bool { false }
+    /// This is run when the scene is put on the stack, to add its objects
+    fn enter(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet) -> GameResult { Ok(()) }
+    /// This is run when the scene has been taken off the stack, and has slid out if it slides,
+    /// before its objects are removed
+    fn leave(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet) -> GameResult { Ok(()) }
+    /// This is run every tick the scene is updated
+    fn tick(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _delta: f32) -> GameResult { Ok(()) }
+    /// This is run every update with the real time since the last one, even while paused
+    fn update_unscaled(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _delta: f32) -> GameResult { Ok(()) }
+    /// This is run for every collision between the objects of the scene each tick
+    fn collision(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _: &Collision) -> GameResult { Ok(()) }
+    /// This is run when an action of the scene's context goes down
+    fn key_down(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _name: &str) -> GameResult { Ok(()) }
+    /// This is run when an action of the scene's context goes up
+    fn key_up(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _name: &str) -> GameResult { Ok(()) }
+    /// This is run when an action of the scene's context is pressed, including repeats
+    fn key_press(&mut self, _: &mut Context, _: &mut State, _: &mut ObjectSet, _name: &str) -> GameResult { Ok(()) }
+    /// This should draw the things of the scene that follow the camera
+    fn draw(&self, _: &mut Context, _: &State, _: &ObjectSet, _alpha: f32) -> GameResult { Ok(()) }
+    /// This should draw the things of the scene that do not follow the camera
+    fn draw_hud(&self, _: &mut Context, _: &State, _: &ObjectSet) -> GameResult { Ok(()) }
+}
+
+/// The edge of the screen a scene slides in from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Side {
+    /// How far a scene is moved to be just off the screen on this side
+    pub(crate) fn offset(self, width: f32, height: f32) -> Vector2 {
+        match self {
+            Side::Left => Vector2::new(-width, 0.),
+            Side::Right => Vector2::new(width, 0.),
+            Side::Top => Vector2::new(0., -height),
+            Side::Bottom => Vector2::new(0., height),
+        }
+    }
+}
+
+/// How the screen goes from one scene to the next
+///
+/// Transitions go by a tick's time every tick, so a replay changes scenes on the same ticks.
+/// While the game is paused they go by in real time instead, so menus can still come up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    /// Changes scenes right away
+    Cut,
+    /// Fades to a colour and back over `secs` seconds, changing scenes halfway
+    Fade {
+        secs: f32,
+        color: Color,
+    },
+    /// Slides the new scene in from a side over `secs` seconds,
+    /// or the popped one out towards it
+    Slide {
+        secs: f32,
+        from: Side,
+    },
+}
+
+impl Default for Transition {
+    #[inline]
+    fn default() -> Self {
+        Transition::Cut
+    }
+}
+
+impl Transition {
+    #[inline]
+    pub(crate) fn secs(self) -> f32 {
+        match self {
+            Transition::Cut => 0.,
+            Transition::Fade { secs, .. } | Transition::Slide { secs, .. } => secs,
+        }
+    }
+}
+
+/// A change to the scene stack asked for through `State`
+pub(crate) enum SceneChange {
+    Push(Box<dyn Scene>),
+    Pop,
+    Replace(Box<dyn Scene>),
+}
+
+impl Debug for SceneChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SceneChange::Push(_) => "Push",
+            SceneChange::Pop => "Pop",
+            SceneChange::Replace(_) => "Replace",
+        })
+    }
+}
+
+/// A scene on the stack along with its objects
+pub(crate) struct SceneEntry {
+    pub(crate) scene: Box<dyn Scene>,
+    pub(crate) object_set: ObjectSet,
+    /// The queue for the scene's objects, swapped into `State` while the scene runs
+    commands: Commands,
+    collisions: CollisionTracker,
+}
+
+impl Debug for SceneEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SceneEntry")
+            .field("object_set", &self.object_set)
+            .finish()
+    }
+}
+
+impl SceneEntry {
+    fn new(scene: Box<dyn Scene>) -> Self {
+        let object_set = ObjectSet::new();
+        SceneEntry {
+            scene,
+            commands: object_set.commands(),
+            object_set,
+            collisions: CollisionTracker::default(),
+        }
+    }
+    /// Lets the scene add its objects
+    pub(crate) fn enter(ctx: &mut Context, state: &mut State, scene: Box<dyn Scene>) -> GameResult<Self> {
+        let mut entry = SceneEntry::new(scene);
+        entry.run(state, |entry, state| entry.scene.enter(ctx, state, &mut entry.object_set))?;
+        entry.object_set.rebuild_spatial_index();
+        Ok(entry)
+    }
+    /// Runs `f` with the commands of `State` going to the scene's objects
+    pub(crate) fn run<R, F: FnOnce(&mut Self, &mut State) -> R>(&mut self, state: &mut State, f: F) -> R {
+        mem::swap(&mut state.commands, &mut self.commands);
+        let ret = f(self, state);
+        self.object_set.apply(&mut state.commands);
+        mem::swap(&mut state.commands, &mut self.commands);
+        ret
+    }
+    /// Lets the scene clean up and removes its objects, running their `on_removed`
+    pub(crate) fn leave(mut self, ctx: &mut Context, state: &mut State) -> GameResult {
+        self.run(state, |entry, state| {
+            entry.scene.leave(ctx, state, &mut entry.object_set)?;
+            entry.object_set.clear();
+            entry.object_set.run_lifecycle(ctx, state);
+            Ok(())
+        })
+    }
+    /// Moves the scene and its objects forward one tick
+    pub(crate) fn tick(&mut self, ctx: &mut Context, state: &mut State, delta: f32) -> GameResult {
+        self.run(state, |entry, state| {
+            entry.object_set.run_lifecycle(ctx, state);
+            entry.object_set.update_each(|obj, objects| obj.update(ctx, state, objects, delta));
+            entry.object_set.apply(&mut state.commands);
+            let contacts = physics::step(&mut entry.object_set, delta);
+            entry.object_set.rebuild_spatial_index();
+
+            for collision in entry.collisions.update(contacts) {
+                if let Some(obj) = entry.object_set.get_dyn_mut(collision.a) {
+                    obj.on_collision(ctx, state, &collision);
+                }
+                if let Some(obj) = entry.object_set.get_dyn_mut(collision.b) {
+                    obj.on_collision(ctx, state, &collision.flipped());
+                }
+                if let Err(e) = entry.scene.collision(ctx, state, &mut entry.object_set, &collision) {
+                    state.error = Some(e);
+                }
+            }
+            entry.object_set.apply(&mut state.commands);
+
+            entry.scene.tick(ctx, state, &mut entry.object_set, delta)
+        })
+    }
+    pub(crate) fn run_lifecycle(&mut self, ctx: &mut Context, state: &mut State) {
+        self.run(state, |entry, state| entry.object_set.run_lifecycle(ctx, state))
+    }
+    pub(crate) fn run_view_hooks(&mut self, ctx: &mut Context, state: &mut State, view: Rect) {
+        self.run(state, |entry, state| entry.object_set.run_view_hooks(ctx, state, view))
+    }
+    pub(crate) fn update_unscaled(&mut self, ctx: &mut Context, state: &mut State, delta: f32) -> GameResult {
+        self.run(state, |entry, state| {
+            entry.object_set.update_each(|obj, objects| obj.update_unscaled(ctx, state, objects, delta));
+            entry.object_set.apply(&mut state.commands);
+            entry.scene.update_unscaled(ctx, state, &mut entry.object_set, delta)
+        })
+    }
+}
+
+/// The lowest layer reached from the top of `scenes` through the scenes that `through` says let it,
+/// where 0 is the game and `n` is the `n`th scene
+pub(crate) fn lowest_layer<F: Fn(&dyn Scene) -> bool>(scenes: &[SceneEntry], through: F) -> usize {
+    let mut lowest = scenes.len();
+    while lowest > 0 && through(&*scenes[lowest - 1].scene) {
+        lowest -= 1;
+    }
+    lowest
+}
+
+/// A scene change that is being shown
+#[derive(Debug)]
+pub(crate) struct Running {
+    pub(crate) transition: Transition,
+    /// Seconds since the transition started
+    pub(crate) elapsed: f32,
+    /// The change waiting for the halfway point of a fade
+    pub(crate) pending: Option<SceneChange>,
+    /// Whether the change puts a new scene on top, which slides in
+    pub(crate) entering: bool,
+    /// The scene taken off the stack, which slides out
+    pub(crate) leaving: Option<SceneEntry>,
+}
+
+impl Running {
+    /// How far along the transition is, from 0 to 1
+    #[inline]
+    pub(crate) fn progress(&self) -> f32 {
+        let secs = self.transition.secs();
+        if secs > 0. {
+            (self.elapsed / secs).min(1.)
+        } else {
+            1.
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textures::Textures;
+    use crate::input::context::DEFAULT_CONTEXT;
+
+    /// A scene with a context
+    struct Menu {
+        context: &'static str,
+        below: bool,
+    }
+
+    impl Scene for Menu {
+        fn context(&self) -> Option<&'static str> { Some(self.context) }
+        fn update_below(&self) -> bool { self.below }
+    }
+
+    fn menu(context: &'static str, below: bool) -> SceneEntry {
+        SceneEntry::new(Box::new(Menu { context, below }))
+    }
+
+    fn state<'a>() -> State<'a> {
+        State::with_size(800., 600., &ObjectSet::new(), 60, Textures::without_assets())
+    }
+
+    #[test]
+    fn changes_wait_in_order_for_the_next_tick() {
+        let mut state = state();
+        state.push_scene(Menu { context: "a", below: false }, Transition::Cut);
+        state.pop_scene(Transition::default());
+        state.replace_scene(Menu { context: "b", below: true }, Transition::Slide { secs: 1., from: Side::Left });
+        let changes: Vec<_> = state.scene_changes.iter().map(|(change, _)| format!("{:?}", change)).collect();
+        assert_eq!(changes, ["Push", "Pop", "Replace"]);
+        assert_eq!(state.top_context(), DEFAULT_CONTEXT);
+    }
+
+    #[test]
+    fn ticks_go_down_through_the_scenes_that_let_them() {
+        let update_below = |scene: &dyn Scene| scene.update_below();
+        assert_eq!(lowest_layer(&[], update_below), 0);
+        // c lets ticks through to a, which stops them from reaching the game
+        let scenes = [menu("a", false), menu("b", true), menu("c", true)];
+        assert_eq!(lowest_layer(&scenes, update_below), 1);
+        let scenes = [menu("a", true), menu("b", true)];
+        assert_eq!(lowest_layer(&scenes, update_below), 0);
+        let scenes = [menu("a", true), menu("b", false)];
+        assert_eq!(lowest_layer(&scenes, update_below), 2);
+    }
+
+    #[test]
+    fn leaving_takes_off_the_scenes_context_under_others() {
+        let mut state = state();
+        state.push_context("menu");
+        // A context pushed on top of the scene's stays when the scene leaves
+        state.push_context("dialog");
+        state.remove_context("menu");
+        assert_eq!(state.top_context(), "dialog");
+        state.pop_context();
+        assert_eq!(state.top_context(), DEFAULT_CONTEXT);
+        state.remove_context(DEFAULT_CONTEXT);
+        assert_eq!(state.top_context(), DEFAULT_CONTEXT);
+    }
+
+    #[test]
+    fn transitions_go_by_in_ticks() {
+        let delta = state().delta();
+        // Half a tick short of four ticks so rounding can't hold a transition back a tick
+        let secs = 3.5 * delta;
+        let mut running = Running {
+            transition: Transition::Fade { secs, color: ggez::graphics::BLACK },
+            elapsed: 0.,
+            pending: Some(SceneChange::Pop),
+            entering: false,
+            leaving: None,
+        };
+        let mut progress = Vec::new();
+        for _ in 0..4 {
+            running.elapsed += delta;
+            progress.push(running.progress());
+        }
+        // A fade changes the scenes on the second tick and is done on the fourth
+        assert!(progress[0] < 0.5 && progress[1] >= 0.5);
+        assert!(progress[2] < 1. && progress[3] >= 1.);
+
+        running.transition = Transition::Cut;
+        assert_eq!(running.progress(), 1.);
+    }
+}
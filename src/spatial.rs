@@ -0,0 +1,355 @@
+//! A uniform grid for finding objects by where they are
+use std::collections::HashMap;
+
+use ggez::graphics::Rect;
+
+use crate::util::{Point2, Vector2};
+use crate::object::{Object, ObjectId};
+
+/// How many cells an object can be put in before it's kept aside instead
+const MAX_CELLS_PER_ENTRY: i64 = 256;
+
+/// Objects bucketed into square cells by their bounds
+///
+/// An object is put in every cell its bounds overlap,
+/// unless it overlaps so many that it's checked by every query instead.
+#[derive(Debug, Clone)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<u32>>,
+    /// The entries too big to put in cells
+    large: Vec<u32>,
+    entries: Vec<(ObjectId<dyn Object>, Rect)>,
+    /// The lowest and highest cell coordinates in use
+    min_cell: (i32, i32),
+    max_cell: (i32, i32),
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(64.)
+    }
+}
+
+#[inline]
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.x <= b.x + b.w && b.x <= a.x + a.w && a.y <= b.y + b.h && b.y <= a.y + a.h
+}
+
+/// Distance from a point to the closest point in a rectangle
+#[inline]
+fn distance_to(p: Point2, r: &Rect) -> f32 {
+    let dx = (r.x - p.x).max(p.x - (r.x + r.w)).max(0.);
+    let dy = (r.y - p.y).max(p.y - (r.y + r.h)).max(0.);
+    dx.hypot(dy)
+}
+
+/// How far along the segment from `from` to `from + d` it first touches the rectangle
+#[inline]
+fn segment_hits(from: Point2, d: Vector2, r: &Rect) -> Option<f32> {
+    segment_span(from, d, r).map(|(t0, _)| t0)
+}
+
+/// How far along the segment from `from` to `from + d` it enters and leaves the rectangle
+fn segment_span(from: Point2, d: Vector2, r: &Rect) -> Option<(f32, f32)> {
+    let (mut t0, mut t1) = (0f32, 1f32);
+    for &(o, dd, min, max) in &[(from.x, d.x, r.x, r.x + r.w), (from.y, d.y, r.y, r.y + r.h)] {
+        if dd == 0. {
+            if o < min || o > max {
+                return None;
+            }
+        } else {
+            let (a, b) = ((min - o) / dd, (max - o) / dd);
+            let (a, b) = if a > b { (b, a) } else { (a, b) };
+            t0 = t0.max(a);
+            t1 = t1.min(b);
+            if t0 > t1 {
+                return None;
+            }
+        }
+    }
+    Some((t0, t1))
+}
+
+impl SpatialHash {
+    /// # Panics
+    ///
+    /// Panics if `cell_size` isn't a positive finite number.
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0. && cell_size.is_finite(), "cell size must be positive and finite, not {}", cell_size);
+        SpatialHash {
+            cell_size,
+            cells: HashMap::new(),
+            large: Vec::new(),
+            entries: Vec::new(),
+            min_cell: (0, 0),
+            max_cell: (0, 0),
+        }
+    }
+    #[inline]
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.large.clear();
+        self.entries.clear();
+    }
+    #[inline]
+    fn cell(&self, x: f32, y: f32) -> (i32, i32) {
+        ((x / self.cell_size).floor() as i32, (y / self.cell_size).floor() as i32)
+    }
+    pub fn insert(&mut self, id: ObjectId<dyn Object>, bounds: Rect) {
+        let index = self.entries.len() as u32;
+        self.entries.push((id, bounds));
+        let (x0, y0) = self.cell(bounds.x, bounds.y);
+        let (x1, y1) = self.cell(bounds.x + bounds.w, bounds.y + bounds.h);
+
+        let cells = (i64::from(x1) - i64::from(x0) + 1) * (i64::from(y1) - i64::from(y0) + 1);
+        if cells > MAX_CELLS_PER_ENTRY {
+            self.large.push(index);
+            return;
+        }
+        if self.cells.is_empty() {
+            self.min_cell = (x0, y0);
+            self.max_cell = (x1, y1);
+        } else {
+            self.min_cell = (self.min_cell.0.min(x0), self.min_cell.1.min(y0));
+            self.max_cell = (self.max_cell.0.max(x1), self.max_cell.1.max(y1));
+        }
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                self.cells.entry((x, y)).or_default().push(index);
+            }
+        }
+    }
+    /// The entries in the given cells and the large entries, without duplicates
+    fn entries_in<I: IntoIterator<Item=(i32, i32)>>(&self, cells: I) -> Vec<u32> {
+        let mut indices: Vec<_> = cells.into_iter()
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .chain(&self.large)
+            .copied()
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+    fn entries_in_rect(&self, rect: &Rect) -> Vec<u32> {
+        if self.cells.is_empty() {
+            return self.large.clone();
+        }
+        // Only walk the cells that can have anything in them
+        let (x0, y0) = self.cell(rect.x, rect.y);
+        let (x1, y1) = self.cell(rect.x + rect.w, rect.y + rect.h);
+        let (x0, y0) = (x0.max(self.min_cell.0), y0.max(self.min_cell.1));
+        let (x1, y1) = (x1.min(self.max_cell.0), y1.min(self.max_cell.1));
+        self.entries_in((x0..=x1).flat_map(|x| (y0..=y1).map(move |y| (x, y))))
+    }
+    /// Finds the objects whose bounds overlap the rectangle
+    pub fn query_rect(&self, rect: Rect) -> Vec<ObjectId<dyn Object>> {
+        self.entries_in_rect(&rect)
+            .into_iter()
+            .map(|i| self.entries[i as usize])
+            .filter(|(_, bounds)| overlaps(&rect, bounds))
+            .map(|(id, _)| id)
+            .collect()
+    }
+    /// Finds the objects whose bounds are within `radius` of `centre`
+    pub fn query_circle(&self, centre: Point2, radius: f32) -> Vec<ObjectId<dyn Object>> {
+        let rect = Rect::new(centre.x - radius, centre.y - radius, 2. * radius, 2. * radius);
+        self.entries_in_rect(&rect)
+            .into_iter()
+            .map(|i| self.entries[i as usize])
+            .filter(|(_, bounds)| distance_to(centre, bounds) <= radius)
+            .map(|(id, _)| id)
+            .collect()
+    }
+    /// Finds the object whose bounds are closest to `p`
+    pub fn nearest(&self, p: Point2) -> Option<ObjectId<dyn Object>> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let mut best: Option<(ObjectId<dyn Object>, f32)> = None;
+        for i in self.entries_in(None) {
+            let (id, bounds) = self.entries[i as usize];
+            let dist = distance_to(p, &bounds);
+            if best.map(|(_, d)| dist < d).unwrap_or(true) {
+                best = Some((id, dist));
+            }
+        }
+        if self.cells.is_empty() {
+            return best.map(|(id, _)| id);
+        }
+
+        // Worked out in i64 since a point far away is as far out as a cell can be,
+        // and the distances from there to the other side of the cells don't fit in i32
+        let wide = |(x, y): (i32, i32)| (i64::from(x), i64::from(y));
+        let (cx, cy) = wide(self.cell(p.x, p.y));
+        let (min_x, min_y) = wide(self.min_cell);
+        let (max_x, max_y) = wide(self.max_cell);
+        // Rings closer in than the occupied cells are empty and rings further out than them are too
+        let min_ring = (min_x - cx).max(cx - max_x).max(min_y - cy).max(cy - max_y).max(0);
+        let max_ring = (cx - min_x).max(max_x - cx).max(cy - min_y).max(max_y - cy).max(0);
+
+        // Look through growing square rings of cells around the point
+        for ring in min_ring..=max_ring {
+            // Only cells between the occupied bounds are made, so they fit in i32 again
+            let ring_cells = ((cx - ring).max(min_x)..=(cx + ring).min(max_x))
+                .flat_map(|x| ((cy - ring).max(min_y)..=(cy + ring).min(max_y)).map(move |y| (x, y)))
+                .filter(|&(x, y)| (x - cx).abs() == ring || (y - cy).abs() == ring)
+                .map(|(x, y)| (x as i32, y as i32));
+
+            for i in self.entries_in(ring_cells) {
+                let (id, bounds) = self.entries[i as usize];
+                let dist = distance_to(p, &bounds);
+                if best.map(|(_, d)| dist < d).unwrap_or(true) {
+                    best = Some((id, dist));
+                }
+            }
+            // Anything further out is at least this far away
+            if let Some((_, d)) = best {
+                if d <= ring as f32 * self.cell_size {
+                    break;
+                }
+            }
+        }
+        best.map(|(id, _)| id)
+    }
+    /// Finds the objects whose bounds the line segment from `from` to `to` goes through
+    ///
+    /// Along with each object is how far along the segment it is hit, from 0 to 1.
+    /// The closest object comes first.
+    pub fn raycast(&self, from: Point2, to: Point2) -> Vec<(ObjectId<dyn Object>, f32)> {
+        let d = to - from;
+
+        // Walk through the cells the segment crosses where there are any
+        let (min_x, min_y) = self.min_cell;
+        let (max_x, max_y) = self.max_cell;
+        let occupied = Rect::new(
+            min_x as f32 * self.cell_size,
+            min_y as f32 * self.cell_size,
+            (max_x - min_x + 1) as f32 * self.cell_size,
+            (max_y - min_y + 1) as f32 * self.cell_size,
+        );
+        let span = if self.cells.is_empty() { None } else { segment_span(from, d, &occupied) };
+        let mut cells = Vec::new();
+        if let Some((t0, t1)) = span {
+            self.walk_cells(from, d, t0, t1, &mut cells);
+        }
+
+        let mut hits: Vec<_> = self.entries_in(cells)
+            .into_iter()
+            .filter_map(|i| {
+                let (id, bounds) = self.entries[i as usize];
+                segment_hits(from, d, &bounds).map(|t| (id, t))
+            })
+            .collect();
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        hits
+    }
+    /// Collects the cells the segment from `from` to `from + d` crosses between `t0` and `t1`
+    fn walk_cells(&self, from: Point2, d: Vector2, t0: f32, t1: f32, cells: &mut Vec<(i32, i32)>) {
+        let clamp = |(x, y): (i32, i32)| (
+            x.max(self.min_cell.0).min(self.max_cell.0),
+            y.max(self.min_cell.1).min(self.max_cell.1),
+        );
+        let (start, end) = (from + d * t0, from + d * t1);
+        let (mut x, mut y) = clamp(self.cell(start.x, start.y));
+        let end = clamp(self.cell(end.x, end.y));
+        let step = |d: f32| if d > 0. { 1 } else { -1 };
+        let first = |cell: i32, o: f32, d: f32| {
+            if d == 0. {
+                f32::INFINITY
+            } else {
+                let edge = if d > 0. { cell + 1 } else { cell };
+                (edge as f32 * self.cell_size - o) / d
+            }
+        };
+        let (step_x, step_y) = (step(d.x), step(d.y));
+        let (mut t_x, mut t_y) = (first(x, from.x, d.x), first(y, from.y, d.y));
+        let (delta_x, delta_y) = (self.cell_size / d.x.abs(), self.cell_size / d.y.abs());
+        loop {
+            cells.push((x, y));
+            if (x, y) == end || (t_x > t1 && t_y > t1) {
+                break;
+            }
+            if t_x < t_y {
+                x += step_x;
+                t_x += delta_x;
+            } else {
+                y += step_y;
+                t_y += delta_y;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(index: u32) -> ObjectId<dyn Object> {
+        ObjectId::from_bits(u64::from(index))
+    }
+
+    /// Three boxes in a row and one huge one far off
+    fn grid() -> SpatialHash {
+        let mut spatial = SpatialHash::new(10.);
+        spatial.insert(id(0), Rect::new(0., 0., 5., 5.));
+        spatial.insert(id(1), Rect::new(20., 0., 5., 5.));
+        spatial.insert(id(2), Rect::new(-40., 0., 5., 5.));
+        spatial.insert(id(3), Rect::new(1e6, 1e6, 1e5, 1e5));
+        spatial
+    }
+
+    #[test]
+    fn query_rect_finds_overlapping_bounds() {
+        let spatial = grid();
+        assert_eq!(spatial.query_rect(Rect::new(-1., -1., 22., 2.)), [id(0), id(1)]);
+        assert_eq!(spatial.query_rect(Rect::new(-1e9, -1e9, 2e9, 2e9)), [id(0), id(1), id(2), id(3)]);
+        assert_eq!(spatial.query_rect(Rect::new(1.05e6, 1.05e6, 1., 1.)), [id(3)]);
+        assert!(spatial.query_rect(Rect::new(6., 6., 10., 10.)).is_empty());
+    }
+
+    #[test]
+    fn query_circle_finds_bounds_within_radius() {
+        let spatial = grid();
+        assert_eq!(spatial.query_circle(Point2::new(12.5, 2.5), 7.5), [id(0), id(1)]);
+        assert!(spatial.query_circle(Point2::new(12.5, 2.5), 7.4).is_empty());
+        assert_eq!(spatial.query_circle(Point2::new(-37.5, 10.), 5.), [id(2)]);
+    }
+
+    #[test]
+    fn nearest_finds_closest_bounds() {
+        let spatial = grid();
+        assert_eq!(spatial.nearest(Point2::new(12., 2.)), Some(id(0)));
+        assert_eq!(spatial.nearest(Point2::new(16., 2.)), Some(id(1)));
+        assert_eq!(spatial.nearest(Point2::new(-1000., 0.)), Some(id(2)));
+        assert_eq!(spatial.nearest(Point2::new(2e6, 2e6)), Some(id(3)));
+        // Further out than a cell can be, on either side of the cells
+        assert_eq!(spatial.nearest(Point2::new(1e12, 0.)), Some(id(3)));
+        assert_eq!(spatial.nearest(Point2::new(-1e12, 0.)), Some(id(2)));
+        assert_eq!(SpatialHash::default().nearest(Point2::new(0., 0.)), None);
+    }
+
+    #[test]
+    fn raycast_finds_hits_closest_first() {
+        let spatial = grid();
+        let hits = spatial.raycast(Point2::new(100., 2.), Point2::new(-100., 2.));
+        let ids: Vec<_> = hits.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, [id(1), id(0), id(2)]);
+        assert!((hits[0].1 - 0.375).abs() < 1e-6);
+
+        assert!(spatial.raycast(Point2::new(0., 7.), Point2::new(30., 7.)).is_empty());
+        // A ray far longer than the grid only walks the cells in use
+        let hits = spatial.raycast(Point2::new(-1e7, 1.), Point2::new(1e7, 1.));
+        assert_eq!(hits.len(), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn cells_have_to_have_a_size() {
+        SpatialHash::new(0.);
+    }
+}
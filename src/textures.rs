@@ -24,6 +24,15 @@ impl Textures {
             font: Font::new(ctx, "/DroidSansMono.ttf")?,
         })
     }
+    /// Makes the assets without loading anything, using the default font
+    ///
+    /// Images are still loaded when first drawn.
+    pub fn without_assets() -> Self {
+        Textures {
+            texes: RefCell::new(HashMap::new()),
+            font: Font::default(),
+        }
+    }
     /// Gets the `Image` to draw from the sprite
     #[inline]
     pub fn get_img(&self, ctx: &mut Context, s: &str) -> Ref<Image> {